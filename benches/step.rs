@@ -0,0 +1,91 @@
+// Times the sliding-window `Automata::step` against `step_reference` on random rules and a
+// few Generations rules. Run with `cargo bench --bench step`; tests/step.rs checks that the
+// two agree.
+
+use cellular_automata::automata::Automata;
+use cellular_automata::neighborhood::Neighborhood;
+use cellular_automata::rulestring::parse_generations;
+use std::time::{Duration, Instant};
+
+const STEPS: usize = 50;
+const GENERATIONS_RULES: [&str; 3] = ["B2/S/C3", "345/2/4", "B3/S23/C2"];
+
+fn rectangle(width: usize, height: usize) -> Neighborhood {
+    Neighborhood::Rectangle { width, height }
+}
+
+fn main() {
+    let cases = [
+        (1024, 1, rectangle(3, 1), 2),
+        (1024, 1, rectangle(16, 1), 2),
+        (256, 256, rectangle(3, 3), 2),
+        (256, 256, rectangle(4, 4), 2),
+        (97, 61, rectangle(5, 2), 2),
+        (8, 5, rectangle(16, 1), 2),
+        (256, 256, Neighborhood::VonNeumann(2), 2),
+        (256, 256, Neighborhood::Hexagonal, 2),
+        (256, 256, Neighborhood::Circular(2), 2),
+        (97, 61, Neighborhood::Cross(3), 2),
+        (
+            97,
+            61,
+            Neighborhood::custom([(-3, 2), (0, 0), (2, -3), (2, 1), (2, 3), (5, 0)]).unwrap(),
+            2,
+        ),
+        (1024, 1, rectangle(3, 1), 3),
+        (1024, 1, rectangle(5, 1), 5),
+        (256, 256, rectangle(2, 2), 16),
+        (256, 256, Neighborhood::VonNeumann(1), 4),
+        (256, 256, Neighborhood::Hexagonal, 3),
+    ];
+
+    for (grid_w, grid_h, neighborhood, states) in cases {
+        let automata = Automata::with_states(grid_w, grid_h, neighborhood.clone(), states, 0.5);
+        let description = format!(
+            "{:>4}x{:<4} board, {:<20} {:>3} states",
+            grid_w,
+            grid_h,
+            neighborhood.label(),
+            states
+        );
+        time(&automata, &description);
+    }
+
+    for rulestring in GENERATIONS_RULES {
+        let rule = parse_generations(rulestring).unwrap();
+        let mut automata = Automata::with_neighborhood(256, 256, Neighborhood::Moore(1), 0.5);
+        let states = rule.states();
+        automata.set_generations(rule);
+        automata.randomize();
+        let description = format!(
+            "{:>4}x{:<4} board, {:<20} {:>3} states",
+            256, 256, rulestring, states
+        );
+        time(&automata, &description);
+    }
+}
+
+fn time(automata: &Automata, description: &str) {
+    let mut sliding = automata.clone();
+    let mut reference = automata.clone();
+
+    let mut sliding_time = Duration::ZERO;
+    let mut reference_time = Duration::ZERO;
+    for _ in 0..STEPS {
+        let start = Instant::now();
+        sliding.step();
+        sliding_time += start.elapsed();
+
+        let start = Instant::now();
+        reference.step_reference();
+        reference_time += start.elapsed();
+    }
+
+    println!(
+        "{}: sliding {:>9.3?}  reference {:>9.3?}  ({:.1}x)",
+        description,
+        sliding_time / STEPS as u32,
+        reference_time / STEPS as u32,
+        reference_time.as_secs_f64() / sliding_time.as_secs_f64().max(f64::EPSILON)
+    );
+}
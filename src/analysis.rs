@@ -0,0 +1,317 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::automata::Automata;
+
+// Spatial entropy counts horizontal blocks of this many cells.
+const ENTROPY_BLOCK: usize = 3;
+// A run whose damage covers less of its light cone than this stayed local.
+const LOCAL_DAMAGE: f64 = 0.02;
+// Chaotic runs look random: high block entropy and damage across much of the light cone.
+const CHAOTIC_ENTROPY: f64 = 0.7;
+const CHAOTIC_DAMAGE: f64 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Behavior {
+    // Every cell ends in the same state, usually 0.
+    DiesOut,
+    // The board stops changing.
+    Freezes,
+    // The board repeats with a period above 1, or its damage never spreads.
+    Cycles,
+    Chaotic,
+    // Neither settles nor looks random: damage spreads but the board keeps structure.
+    Complex,
+}
+
+impl Behavior {
+    pub fn label(self) -> &'static str {
+        match self {
+            Behavior::DiesOut => "dies out",
+            Behavior::Freezes => "freezes",
+            Behavior::Cycles => "cycles",
+            Behavior::Chaotic => "chaotic",
+            Behavior::Complex => "complex",
+        }
+    }
+
+    // Wolfram's class number.
+    pub fn class(self) -> &'static str {
+        match self {
+            Behavior::DiesOut => "I",
+            Behavior::Freezes | Behavior::Cycles => "II",
+            Behavior::Chaotic => "III",
+            Behavior::Complex => "IV",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Run {
+    pub board_seed: u64,
+    // Fewer than asked for when the board repeated.
+    pub steps: usize,
+    pub final_density: f64,
+    // Over the boards after the first, or the first alone when no step was run.
+    pub mean_density: f64,
+    // Mean density over the last quarter of the run minus the first quarter.
+    pub density_trend: f64,
+    // Fraction of cells that changed on the last step.
+    pub activity: f64,
+    pub transient: Option<usize>,
+    pub period: Option<usize>,
+    // Block entropy of the final board, from 0 (uniform) to 1 (random).
+    pub entropy: f64,
+    // Cells that differ from a copy that started with one flipped cell, as a fraction of the
+    // cells that flip could have reached.
+    pub damage: f64,
+    pub behavior: Behavior,
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub runs: Vec<Run>,
+    // The most common behavior over the runs; ties go to the later one in `Behavior`.
+    pub behavior: Behavior,
+}
+
+// Runs a copy of `automata` for `steps` steps from each board seed. The rules, boundary and
+// spawn chance are kept; only the board is reseeded.
+pub fn classify(automata: &Automata, steps: usize, board_seeds: &[u64]) -> Report {
+    let runs: Vec<Run> = board_seeds
+        .iter()
+        .map(|&seed| run(automata, steps, seed))
+        .collect();
+    let mut counts: Vec<(Behavior, usize)> = Vec::new();
+    for run in &runs {
+        match counts
+            .iter_mut()
+            .find(|(behavior, _)| *behavior == run.behavior)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((run.behavior, 1)),
+        }
+    }
+    let behavior = counts
+        .into_iter()
+        .max_by_key(|&(behavior, count)| (count, behavior))
+        .map_or(Behavior::DiesOut, |(behavior, _)| behavior);
+    Report { runs, behavior }
+}
+
+fn run(automata: &Automata, steps: usize, board_seed: u64) -> Run {
+    let mut automata = automata.clone();
+    automata.reseed_board(board_seed);
+    let (grid_w, grid_h) = automata.size();
+    let states = automata.states();
+
+    let mut damaged = automata.clone();
+    let (x, y) = (grid_w / 2, grid_h / 2);
+    let centre = automata.get_cell(x, y).unwrap_or(0);
+    damaged.set_cell(x, y, ((centre as usize + 1) % states) as u8);
+
+    let start = automata.clone();
+    let mut cells = automata.cells();
+    let mut densities = vec![density(&cells)];
+    let mut seen = HashMap::new();
+    seen.insert(board_hash(&cells), 0);
+    let mut cycle = None;
+    let mut steps_run = 0;
+    let mut activity = 0.0;
+    for step in 1..=steps {
+        automata.step();
+        damaged.step();
+        steps_run = step;
+        let next = automata.cells();
+        let changed = cells.iter().zip(&next).filter(|(a, b)| a != b).count();
+        activity = changed as f64 / next.len() as f64;
+        cells = next;
+        densities.push(density(&cells));
+        if let Some(first) = seen.insert(board_hash(&cells), step) {
+            if board_at(&start, first) == cells {
+                cycle = Some((first, step - first));
+                break;
+            }
+        }
+    }
+
+    let quarter = densities.len().div_ceil(4);
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let density_trend = mean(&densities[densities.len() - quarter..]) - mean(&densities[..quarter]);
+
+    let differing = cells
+        .iter()
+        .zip(damaged.cells())
+        .filter(|&(&a, b)| a != b)
+        .count();
+    let damage = differing as f64 / light_cone(&automata, steps_run) as f64;
+    let entropy = block_entropy(&cells, grid_w, states);
+
+    let behavior = if cells.iter().all(|&state| state == cells[0]) {
+        Behavior::DiesOut
+    } else if let Some((_, period)) = cycle {
+        if period == 1 {
+            Behavior::Freezes
+        } else {
+            Behavior::Cycles
+        }
+    } else if damage < LOCAL_DAMAGE {
+        Behavior::Cycles
+    } else if entropy >= CHAOTIC_ENTROPY && damage >= CHAOTIC_DAMAGE {
+        Behavior::Chaotic
+    } else {
+        Behavior::Complex
+    };
+
+    Run {
+        board_seed,
+        steps: steps_run,
+        final_density: density(&cells),
+        mean_density: mean(&densities[densities.len().min(2) - 1..]),
+        density_trend,
+        activity,
+        transient: cycle.map(|(first, _)| first),
+        period: cycle.map(|(_, period)| period),
+        entropy,
+        damage,
+        behavior,
+    }
+}
+
+// Hashes only say a board may have been seen; stepping is deterministic, so the earlier board
+// is rebuilt from the start to make sure.
+fn board_at(start: &Automata, step: usize) -> Vec<u8> {
+    let mut automata = start.clone();
+    for _ in 0..step {
+        automata.step();
+    }
+    automata.cells()
+}
+
+// Cells within `steps` neighborhood reaches of the board centre, clipped to the board.
+fn light_cone(automata: &Automata, steps: usize) -> usize {
+    let (grid_w, grid_h) = automata.size();
+    let offsets = automata.neighborhood().offsets();
+    let reach_x = offsets
+        .iter()
+        .map(|&(dx, _)| dx.unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    let reach_y = offsets
+        .iter()
+        .map(|&(_, dy)| dy.unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    let cone_w = (2 * reach_x * steps + 1).min(grid_w);
+    let cone_h = (2 * reach_y * steps + 1).min(grid_h);
+    cone_w * cone_h
+}
+
+// Shannon entropy of the horizontal blocks on every row, wrapping at the row end, divided by
+// the entropy of a random board.
+fn block_entropy(cells: &[u8], grid_w: usize, states: usize) -> f64 {
+    let block = ENTROPY_BLOCK.min(grid_w);
+    let max_entropy = block as f64 * (states as f64).log2();
+    if max_entropy == 0.0 {
+        return 0.0;
+    }
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for row in cells.chunks(grid_w) {
+        for x in 0..grid_w {
+            let key = (0..block).fold(0, |key, i| {
+                key * states as u64 + row[(x + i) % grid_w] as u64
+            });
+            *counts.entry(key).or_insert(0) += 1;
+        }
+    }
+    let total = cells.len() as f64;
+    let entropy: f64 = counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    entropy / max_entropy
+}
+
+// Fraction of non-zero cells.
+fn density(cells: &[u8]) -> f64 {
+    cells.iter().filter(|&&state| state != 0).count() as f64 / cells.len() as f64
+}
+
+fn board_hash(cells: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    cells.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neighborhood::Neighborhood;
+    use crate::rules::RulesCollection;
+    use num_bigint::BigUint;
+
+    const SEEDS: [u64; 4] = [1, 2, 3, 4];
+
+    fn elementary(code: u32, width: usize) -> Automata {
+        let neighborhood = Neighborhood::Rectangle {
+            width: 3,
+            height: 1,
+        };
+        let rules =
+            RulesCollection::from_wolfram_code(&BigUint::from(code), neighborhood.clone(), 2)
+                .unwrap();
+        let mut automata = Automata::with_seeds(width, 1, neighborhood, 2, 0.5, 0, 0);
+        automata.set_rules(rules);
+        automata
+    }
+
+    fn mean_damage(report: &Report) -> f64 {
+        report.runs.iter().map(|run| run.damage).sum::<f64>() / report.runs.len() as f64
+    }
+
+    #[test]
+    fn elementary_rules_fall_in_their_classes() {
+        assert_eq!(
+            classify(&elementary(0, 100), 64, &SEEDS).behavior,
+            Behavior::DiesOut
+        );
+        assert_eq!(
+            classify(&elementary(4, 100), 64, &SEEDS).behavior,
+            Behavior::Freezes
+        );
+        assert_eq!(
+            classify(&elementary(30, 100), 256, &SEEDS).behavior,
+            Behavior::Chaotic
+        );
+    }
+
+    #[test]
+    fn rule_110_is_complex_on_wide_boards() {
+        // Small boards fill with gliders before long and read as chaotic.
+        let rule_110 = classify(&elementary(110, 512), 256, &SEEDS);
+        assert_eq!(rule_110.behavior, Behavior::Complex);
+        let rule_30 = classify(&elementary(30, 512), 256, &SEEDS);
+        assert!(mean_damage(&rule_110) < mean_damage(&rule_30));
+    }
+
+    #[test]
+    fn cycles_are_found_with_their_period() {
+        // Rule 170 shifts the board one cell left every step.
+        let report = classify(&elementary(170, 37), 100, &SEEDS);
+        assert_eq!(report.behavior, Behavior::Cycles);
+        for run in &report.runs {
+            assert_eq!((run.transient, run.period), (Some(0), Some(37)));
+            assert_eq!(run.steps, 37);
+        }
+
+        let report = classify(&elementary(4, 100), 64, &SEEDS);
+        for run in &report.runs {
+            assert_eq!(run.period, Some(1));
+            assert_eq!(run.activity, 0.0);
+        }
+    }
+}
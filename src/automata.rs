@@ -1,17 +1,136 @@
 use ::rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-use crate::rules::RulesCollection;
+#[cfg(feature = "parallel")]
+use crate::config::PARALLEL_MIN_CELLS;
+use crate::neighborhood::Neighborhood;
+use crate::rules::{RuleOptions, RulesCollection};
+use crate::rulestring::GenerationsRule;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Boundary {
+    Periodic,
+    FixedDead,
+    FixedAlive,
+    Reflecting,
+    ExtendEdge,
+}
+
+impl Boundary {
+    pub const ALL: [Boundary; 5] = [
+        Boundary::Periodic,
+        Boundary::FixedDead,
+        Boundary::FixedAlive,
+        Boundary::Reflecting,
+        Boundary::ExtendEdge,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Boundary::Periodic => "Periodic (wrap)",
+            Boundary::FixedDead => "Fixed dead",
+            Boundary::FixedAlive => "Fixed alive",
+            Boundary::Reflecting => "Reflecting",
+            Boundary::ExtendEdge => "Extend edge",
+        }
+    }
+
+    // Index that a neighbor at `pos` on an axis of `len` cells reads from, or `None` when it
+    // falls on a fixed edge and reads `fixed_state` instead. Reflecting mirrors about the
+    // board edge (-1 reads 0, -2 reads 1) while extend edge repeats the edge cell.
+    fn resolve(self, pos: isize, len: usize) -> Option<usize> {
+        let len = len as isize;
+        if (0..len).contains(&pos) {
+            return Some(pos as usize);
+        }
+        match self {
+            Boundary::Periodic => Some(pos.rem_euclid(len) as usize),
+            Boundary::FixedDead | Boundary::FixedAlive => None,
+            Boundary::Reflecting => {
+                let folded = pos.rem_euclid(2 * len);
+                Some(if folded >= len {
+                    2 * len - 1 - folded
+                } else {
+                    folded
+                } as usize)
+            }
+            Boundary::ExtendEdge => Some(pos.clamp(0, len - 1) as usize),
+        }
+    }
+
+    fn fixed_state(self) -> u8 {
+        (self == Boundary::FixedAlive) as u8
+    }
+}
+
+// The neighborhood's bounding box as seen by `step_band`: every neighborhood cell as a
+// (column, row) position inside the box, listed in hash order.
+#[derive(Clone)]
+struct Window {
+    start_x: isize,
+    start_y: isize,
+    width: usize,
+    height: usize,
+    cells: Vec<(usize, usize)>,
+    used_rows: Vec<bool>,
+    rectangular: bool,
+}
+
+impl Window {
+    fn new(offsets: &[(isize, isize)]) -> Self {
+        let start_x = offsets.iter().map(|&(dx, _)| dx).min().unwrap_or(0);
+        let end_x = offsets.iter().map(|&(dx, _)| dx).max().unwrap_or(0);
+        let start_y = offsets.iter().map(|&(_, dy)| dy).min().unwrap_or(0);
+        let end_y = offsets.iter().map(|&(_, dy)| dy).max().unwrap_or(0);
+        let width = (end_x - start_x + 1) as usize;
+        let height = (end_y - start_y + 1) as usize;
+
+        let cells: Vec<(usize, usize)> = offsets
+            .iter()
+            .map(|&(dx, dy)| ((dx - start_x) as usize, (dy - start_y) as usize))
+            .collect();
+        let mut used_rows = vec![false; height];
+        for &(_, dy_idx) in cells.iter() {
+            used_rows[dy_idx] = true;
+        }
+        // Offsets are unique and listed column by column, so filling the whole box means the
+        // cells are exactly the box in hash order.
+        let rectangular = cells.len() == width * height;
+
+        Self {
+            start_x,
+            start_y,
+            width,
+            height,
+            cells,
+            used_rows,
+            rectangular,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Automata {
-    rng: SmallRng,
+    // The rule table and the board draw from separate generators so either can be reproduced
+    // from its seed without the other.
+    rule_rng: SmallRng,
+    board_rng: SmallRng,
+    rule_seed: u64,
+    board_seed: u64,
     cells: Vec<u8>,
     cells_next: Vec<u8>,
     grid_h: usize,
     grid_w: usize,
     neighborhood_offsets: Vec<(isize, isize)>,
+    window: Window,
     rules_collection: RulesCollection,
     spawn_chance: f32,
+    rule_options: RuleOptions,
+    boundary_x: Boundary,
+    boundary_y: Boundary,
+    // When set, cells follow this Generations rule on the Moore neighborhood instead of the
+    // rule table.
+    generations: Option<GenerationsRule>,
 }
 
 impl Automata {
@@ -22,42 +141,236 @@ impl Automata {
         neighborhood_h: usize,
         spawn_chance: f32,
     ) -> Self {
-        let mut neighborhood_offsets = Vec::new();
-        let start_x = -((neighborhood_w as isize - 1) / 2);
-        let end_x = neighborhood_w as isize / 2;
-        let start_y = -((neighborhood_h as isize - 1) / 2);
-        let end_y = neighborhood_h as isize / 2;
-        for dx in start_x..=end_x {
-            for dy in start_y..=end_y {
-                neighborhood_offsets.push((dx, dy));
-            }
-        }
+        Self::with_neighborhood(
+            grid_w,
+            grid_h,
+            Neighborhood::Rectangle {
+                width: neighborhood_w,
+                height: neighborhood_h,
+            },
+            spawn_chance,
+        )
+    }
 
-        let mut rng = SmallRng::from_entropy();
+    pub fn with_neighborhood(
+        grid_w: usize,
+        grid_h: usize,
+        neighborhood: Neighborhood,
+        spawn_chance: f32,
+    ) -> Self {
+        Self::with_states(grid_w, grid_h, neighborhood, 2, spawn_chance)
+    }
 
-        let mut rules_collection = RulesCollection::new(neighborhood_w, neighborhood_h);
-        rules_collection.randomize(&mut rng);
+    pub fn with_states(
+        grid_w: usize,
+        grid_h: usize,
+        neighborhood: Neighborhood,
+        states: usize,
+        spawn_chance: f32,
+    ) -> Self {
+        Self::with_seeds(
+            grid_w,
+            grid_h,
+            neighborhood,
+            states,
+            spawn_chance,
+            ::rand::random(),
+            ::rand::random(),
+        )
+    }
+
+    pub fn with_seeds(
+        grid_w: usize,
+        grid_h: usize,
+        neighborhood: Neighborhood,
+        states: usize,
+        spawn_chance: f32,
+        rule_seed: u64,
+        board_seed: u64,
+    ) -> Self {
+        let neighborhood_offsets = neighborhood.offsets();
+        let window = Window::new(&neighborhood_offsets);
+
+        let mut rule_rng = SmallRng::seed_from_u64(rule_seed);
+
+        let mut rules_collection = RulesCollection::with_states(neighborhood, states);
+        rules_collection.randomize(&mut rule_rng, &RuleOptions::default());
 
         let mut automata = Self {
-            rng,
+            rule_rng,
+            board_rng: SmallRng::seed_from_u64(board_seed),
+            rule_seed,
+            board_seed,
             cells: vec![0u8; grid_w * grid_h],
             cells_next: vec![0u8; grid_w * grid_h],
             grid_h,
             grid_w,
             neighborhood_offsets,
+            window,
             rules_collection,
             spawn_chance,
+            rule_options: RuleOptions::default(),
+            boundary_x: Boundary::Periodic,
+            boundary_y: Boundary::Periodic,
+            generations: None,
         };
         automata.randomize();
         automata
     }
 
     pub fn step(&mut self) {
+        #[cfg(feature = "parallel")]
+        if self.cells.len() >= PARALLEL_MIN_CELLS {
+            self.step_parallel();
+            return;
+        }
+        self.step_serial();
+    }
+
+    pub fn step_serial(&mut self) {
+        let mut next = std::mem::take(&mut self.cells_next);
+        self.step_band(0, &mut next);
+        self.cells_next = next;
+        std::mem::swap(&mut self.cells, &mut self.cells_next);
+    }
+
+    // Rows only read `cells`, so bands of rows can be written into `cells_next` independently
+    // and the result does not depend on how many threads picked them up.
+    #[cfg(feature = "parallel")]
+    pub fn step_parallel(&mut self) {
+        use rayon::prelude::*;
+
+        let band_rows = self
+            .grid_h
+            .div_ceil(rayon::current_num_threads() * 4)
+            .max(1);
+        let mut next = std::mem::take(&mut self.cells_next);
+        next.par_chunks_mut(band_rows * self.grid_w)
+            .enumerate()
+            .for_each(|(band, rows)| self.step_band(band * band_rows, rows));
+        self.cells_next = next;
+        std::mem::swap(&mut self.cells, &mut self.cells_next);
+    }
+
+    // Each row first copies the states the window will pass over into `window_rows`, with
+    // the boundary already applied. For a rectangular neighborhood each column of the window
+    // is its own group of `height` digits in the hash (leftmost column lowest), so moving one
+    // cell right drops the lowest column and adds the new one on top; other shapes sum their
+    // cells instead.
+    fn step_band(&self, first_row: usize, band: &mut [u8]) {
+        if let Some(rule) = &self.generations {
+            self.step_generations_band(rule, first_row, band);
+            return;
+        }
+
+        let window = &self.window;
+        let states = self.rules_collection.states() as u64;
+        let padded_w = self.grid_w + window.width - 1;
+        let source_columns: Vec<Option<usize>> = (0..padded_w)
+            .map(|padded_x| {
+                self.boundary_x
+                    .resolve(padded_x as isize + window.start_x, self.grid_w)
+            })
+            .collect();
+        let weights: Vec<u64> = (0..window.cells.len())
+            .map(|cell_index| states.pow(cell_index as u32))
+            .collect();
+        let column_weight = states.pow(window.height as u32);
+        let column_shift = states
+            .is_power_of_two()
+            .then(|| states.trailing_zeros() * window.height as u32);
+        let entering_weight = states.pow(((window.width - 1) * window.height) as u32);
+
+        let mut window_rows = vec![0u8; window.height * padded_w];
+        let mut columns = vec![0u64; padded_w];
+        for (row_idx, row) in band.chunks_mut(self.grid_w).enumerate() {
+            let y = (first_row + row_idx) as isize;
+            for (dy_idx, window_row) in window_rows.chunks_mut(padded_w).enumerate() {
+                if !window.used_rows[dy_idx] {
+                    continue;
+                }
+                let neighbor_y = y + window.start_y + dy_idx as isize;
+                let source_row = self.boundary_y.resolve(neighbor_y, self.grid_h);
+                for (state, source_column) in window_row.iter_mut().zip(&source_columns) {
+                    *state = self.state_at(*source_column, source_row);
+                }
+            }
+
+            if window.rectangular {
+                for (padded_x, column) in columns.iter_mut().enumerate() {
+                    *column = (0..window.height).rev().fold(0u64, |acc, dy_idx| {
+                        acc * states + window_rows[dy_idx * padded_w + padded_x] as u64
+                    });
+                }
+                let mut neighborhood = columns[..window.width]
+                    .iter()
+                    .rev()
+                    .fold(0u64, |acc, column| acc * column_weight + column);
+                for (x, cell) in row.iter_mut().enumerate() {
+                    *cell = self.rules_collection.get_new_cell(neighborhood);
+                    if let Some(entering) = columns.get(x + window.width) {
+                        let remaining = match column_shift {
+                            Some(shift) => neighborhood >> shift,
+                            None => neighborhood / column_weight,
+                        };
+                        neighborhood = remaining + entering * entering_weight;
+                    }
+                }
+            } else {
+                for (x, cell) in row.iter_mut().enumerate() {
+                    let neighborhood = window.cells.iter().zip(&weights).fold(
+                        0u64,
+                        |acc, (&(dx_idx, dy_idx), weight)| {
+                            acc + window_rows[dy_idx * padded_w + x + dx_idx] as u64 * weight
+                        },
+                    );
+                    *cell = self.rules_collection.get_new_cell(neighborhood);
+                }
+            }
+        }
+    }
+
+    // Only live (state 1) cells count as neighbors, so each column of the 3x3 window is kept
+    // as its three bits of the row-major mask and the mask is rebuilt from three columns.
+    fn step_generations_band(&self, rule: &GenerationsRule, first_row: usize, band: &mut [u8]) {
+        let padded_w = self.grid_w + 2;
+        let source_columns: Vec<Option<usize>> = (0..padded_w)
+            .map(|padded_x| self.boundary_x.resolve(padded_x as isize - 1, self.grid_w))
+            .collect();
+
+        let mut columns = vec![0u16; padded_w];
+        for (row_idx, row) in band.chunks_mut(self.grid_w).enumerate() {
+            let y = first_row + row_idx;
+            let source_rows =
+                [-1, 0, 1].map(|dy| self.boundary_y.resolve(y as isize + dy, self.grid_h));
+            for (column, source_column) in columns.iter_mut().zip(&source_columns) {
+                *column =
+                    source_rows
+                        .iter()
+                        .enumerate()
+                        .fold(0u16, |acc, (dy_idx, &source_row)| {
+                            let alive = self.state_at(*source_column, source_row) == 1;
+                            acc | (alive as u16) << (dy_idx * 3)
+                        });
+            }
+            for (x, cell) in row.iter_mut().enumerate() {
+                let mask = columns[x] | columns[x + 1] << 1 | columns[x + 2] << 2;
+                *cell = rule.next_state(self.cells[x + y * self.grid_w], mask);
+            }
+        }
+    }
+
+    // Straightforward per-cell version of `step`, kept as the reference it is checked against.
+    pub fn step_reference(&mut self) {
         for x in 0..self.grid_w {
             for y in 0..self.grid_h {
                 let idx = x + y * self.grid_w;
-                let neighborhood = self.get_neighborhood_hash(x, y);
-                self.cells_next[idx] = self.rules_collection.get_new_cell(neighborhood);
+                self.cells_next[idx] = match &self.generations {
+                    Some(rule) => rule.next_state(self.cells[idx], self.get_generations_mask(x, y)),
+                    None => self
+                        .rules_collection
+                        .get_new_cell(self.get_neighborhood_hash(x, y)),
+                };
             }
         }
         std::mem::swap(&mut self.cells, &mut self.cells_next);
@@ -68,14 +381,119 @@ impl Automata {
         self.cells.clone()
     }
 
+    // Row-major like `cells`; states the rule does not have are clamped to the highest one.
+    pub fn set_cells(&mut self, cells: &[u8]) -> Result<(), String> {
+        if cells.len() != self.cells.len() {
+            return Err(format!(
+                "expected {} cells for a {}x{} board, got {}",
+                self.cells.len(),
+                self.grid_w,
+                self.grid_h,
+                cells.len()
+            ));
+        }
+        let max_state = (self.states() - 1) as u8;
+        for (cell, &state) in self.cells.iter_mut().zip(cells) {
+            *cell = state.min(max_state);
+        }
+        Ok(())
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.grid_w, self.grid_h)
+    }
+
+    pub fn get_cell(&self, x: usize, y: usize) -> Option<u8> {
+        (x < self.grid_w && y < self.grid_h).then(|| self.cells[x + y * self.grid_w])
+    }
+
+    pub fn set_cell(&mut self, x: usize, y: usize, state: u8) {
+        self.fill_region(x, y, 1, 1, state);
+    }
+
+    // The region is clipped to the board and states the rule does not have are clamped to the
+    // highest one.
+    pub fn fill_region(&mut self, x: usize, y: usize, width: usize, height: usize, state: u8) {
+        let state = state.min((self.states() - 1) as u8);
+        let x_end = x.saturating_add(width).min(self.grid_w);
+        let y_end = y.saturating_add(height).min(self.grid_h);
+        for row in y.min(y_end)..y_end {
+            let row_start = row * self.grid_w;
+            self.cells[row_start + x.min(x_end)..row_start + x_end].fill(state);
+        }
+    }
+
+    pub fn rules(&self) -> &RulesCollection {
+        &self.rules_collection
+    }
+
+    pub fn rules_mut(&mut self) -> &mut RulesCollection {
+        &mut self.rules_collection
+    }
+
+    // States the new rules do not know about are clamped to their highest state. This also
+    // leaves any Generations rule.
+    pub fn set_rules(&mut self, rules_collection: RulesCollection) {
+        self.generations = None;
+        self.clamp_cells(rules_collection.states());
+        self.neighborhood_offsets = rules_collection.neighborhood().offsets();
+        self.window = Window::new(&self.neighborhood_offsets);
+        self.rules_collection = rules_collection;
+    }
+
     pub fn randomize_rules(&mut self) {
-        self.rules_collection.randomize(&mut self.rng);
+        self.generations = None;
+        self.clamp_cells(self.rules_collection.states());
+        self.rules_collection
+            .randomize(&mut self.rule_rng, &self.rule_options);
+    }
+
+    pub fn rule_seed(&self) -> u64 {
+        self.rule_seed
+    }
+
+    pub fn board_seed(&self) -> u64 {
+        self.board_seed
+    }
+
+    // Restarts the rule generator from `seed` and draws a new table from it, so the same seed,
+    // neighborhood and state count always give the same rule.
+    pub fn reseed_rules(&mut self, seed: u64) {
+        self.rule_seed = seed;
+        self.rule_rng = SmallRng::seed_from_u64(seed);
+        self.randomize_rules();
+    }
+
+    pub fn reseed_board(&mut self, seed: u64) {
+        self.board_seed = seed;
+        self.board_rng = SmallRng::seed_from_u64(seed);
+        self.randomize();
+    }
+
+    pub fn generations(&self) -> Option<&GenerationsRule> {
+        self.generations.as_ref()
     }
 
+    // The rule table and neighborhood are kept for when the Generations rule is left again.
+    pub fn set_generations(&mut self, rule: GenerationsRule) {
+        self.clamp_cells(rule.states());
+        self.generations = Some(rule);
+    }
+
+    fn clamp_cells(&mut self, states: usize) {
+        let max_state = (states - 1) as u8;
+        for c in self.cells.iter_mut() {
+            *c = (*c).min(max_state);
+        }
+    }
+
+    // Each cell comes alive with `spawn_chance`, in a uniformly chosen non-zero state. Under a
+    // Generations rule the higher states are refractory, so new cells are always state 1.
     pub fn randomize(&mut self) {
+        let max_state = self.max_spawn_state();
         for c in self.cells.iter_mut() {
-            *c = if self.rng.gen_bool(self.spawn_chance as f64) {
-                1
+            *c = if self.board_rng.gen_bool(self.spawn_chance as f64) {
+                self.board_rng.gen_range(1..=max_state)
             } else {
                 0
             };
@@ -83,44 +501,100 @@ impl Automata {
     }
 
     pub fn randomize_next(&mut self) {
+        let max_state = self.max_spawn_state();
         for c in self.cells_next.iter_mut() {
-            *c = if self.rng.gen_bool(self.spawn_chance as f64) {
-                1
+            *c = if self.board_rng.gen_bool(self.spawn_chance as f64) {
+                self.board_rng.gen_range(1..=max_state)
             } else {
                 0
             };
         }
     }
 
+    fn max_spawn_state(&self) -> u8 {
+        match self.generations {
+            Some(_) => 1,
+            None => (self.rules_collection.states() - 1) as u8,
+        }
+    }
+
+    pub fn states(&self) -> usize {
+        match &self.generations {
+            Some(rule) => rule.states(),
+            None => self.rules_collection.states(),
+        }
+    }
+
+    pub fn boundary(&self) -> (Boundary, Boundary) {
+        (self.boundary_x, self.boundary_y)
+    }
+
+    pub fn set_boundary(&mut self, boundary_x: Boundary, boundary_y: Boundary) {
+        self.boundary_x = boundary_x;
+        self.boundary_y = boundary_y;
+    }
+
+    pub fn spawn_chance(&self) -> f32 {
+        self.spawn_chance
+    }
+
+    pub fn rule_options(&self) -> &RuleOptions {
+        &self.rule_options
+    }
+
+    // Applies from the next random table on; see `reseed_rules`.
+    pub fn set_rule_options(&mut self, rule_options: RuleOptions) {
+        self.rule_options = rule_options;
+    }
+
     pub fn set_spawn_chance(&mut self, spawn_chance: f32) {
         self.spawn_chance = spawn_chance.clamp(0.0, 1.0);
     }
 
-    pub fn set_neighborhood_size(&mut self, neighborhood_w: usize, neighborhood_h: usize) {
-        self.neighborhood_offsets.clear();
-        let start_x = -((neighborhood_w as isize - 1) / 2);
-        let end_x = neighborhood_w as isize / 2;
-        let start_y = -((neighborhood_h as isize - 1) / 2);
-        let end_y = neighborhood_h as isize / 2;
-        for dx in start_x..=end_x {
-            for dy in start_y..=end_y {
-                self.neighborhood_offsets.push((dx, dy));
-            }
-        }
-        self.rules_collection
-            .set_neighborhood_size(neighborhood_w, neighborhood_h, &mut self.rng);
+    pub fn neighborhood(&self) -> &Neighborhood {
+        self.rules_collection.neighborhood()
+    }
+
+    // The new table is drawn from the rule seed again, not from wherever the generator was.
+    pub fn set_neighborhood(&mut self, neighborhood: Neighborhood) {
+        self.neighborhood_offsets = neighborhood.offsets();
+        self.window = Window::new(&self.neighborhood_offsets);
+        self.rule_rng = SmallRng::seed_from_u64(self.rule_seed);
+        self.rules_collection.set_neighborhood(
+            neighborhood,
+            &mut self.rule_rng,
+            &self.rule_options,
+        );
     }
 
-    fn get_neighborhood_hash(&mut self, x: usize, y: usize) -> u64 {
+    fn get_neighborhood_hash(&self, x: usize, y: usize) -> u64 {
+        let states = self.rules_collection.states() as u64;
         self.neighborhood_offsets
             .iter()
-            .enumerate()
-            .map(|(cell_index, (dx, dy))| {
-                let neighbor_x = (x as isize + dx).rem_euclid(self.grid_w as isize);
-                let neighbor_y = (y as isize + dy).rem_euclid(self.grid_h as isize);
-                let idx = (neighbor_x + neighbor_y * self.grid_w as isize) as usize;
-                (self.cells[idx] as u64) << cell_index
-            })
-            .fold(0u64, |acc, val| acc | val)
+            .rev()
+            .map(|(dx, dy)| self.neighbor_state(x, y, *dx, *dy) as u64)
+            .fold(0u64, |acc, state| acc * states + state)
+    }
+
+    fn get_generations_mask(&self, x: usize, y: usize) -> u16 {
+        (0..9).fold(0u16, |acc, idx| {
+            let alive = self.neighbor_state(x, y, idx % 3 - 1, idx / 3 - 1) == 1;
+            acc | (alive as u16) << idx
+        })
+    }
+
+    fn neighbor_state(&self, x: usize, y: usize, dx: isize, dy: isize) -> u8 {
+        let neighbor_x = self.boundary_x.resolve(x as isize + dx, self.grid_w);
+        let neighbor_y = self.boundary_y.resolve(y as isize + dy, self.grid_h);
+        self.state_at(neighbor_x, neighbor_y)
+    }
+
+    // A missing coordinate means the neighbor is past a fixed edge; the x axis wins when both are.
+    fn state_at(&self, x: Option<usize>, y: Option<usize>) -> u8 {
+        match (x, y) {
+            (Some(x), Some(y)) => self.cells[x + y * self.grid_w],
+            (None, _) => self.boundary_x.fixed_state(),
+            (_, None) => self.boundary_y.fixed_state(),
+        }
     }
 }
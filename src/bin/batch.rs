@@ -0,0 +1,188 @@
+#![deny(non_snake_case)]
+
+// Runs many random rules without a window and prints one line of statistics per rule, so rule
+// space can be mined on machines without a display. Rule `i` uses rule seed `seed + i`, and
+// every rule starts from the same board.
+
+use cellular_automata::analysis::{self, Behavior, Run};
+use cellular_automata::automata::Automata;
+use cellular_automata::config::{
+    ANALYSIS_RUNS, GRID_H, GRID_W, MAX_STATES, NEIGHBORHOOD_H, NEIGHBORHOOD_W, SPAWN_CHANCE, STATES,
+};
+use cellular_automata::neighborhood::Neighborhood;
+use cellular_automata::rules::max_cells;
+
+// Wolfram codes are printed for tables up to this many patterns; larger ones are only
+// identified by their seed.
+const MAX_PRINTED_PATTERNS: u64 = 64;
+
+const USAGE: &str = "usage: batch [--rules N] [--steps N] [--width W] [--height H] \
+[--neighborhood WxH] [--states K] [--spawn P] [--seed S] [--board-seed S]";
+
+struct Settings {
+    rules: u64,
+    steps: usize,
+    grid_w: usize,
+    grid_h: usize,
+    neighborhood: Neighborhood,
+    states: usize,
+    spawn_chance: f32,
+    seed: u64,
+    board_seed: u64,
+}
+
+struct Stats {
+    rule_seed: u64,
+    rule: String,
+    // The run from the shared board.
+    run: Run,
+    behavior: Behavior,
+}
+
+fn main() {
+    let settings = match parse_args(std::env::args().skip(1).collect()) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    println!(
+        "# {}x{} board, {}, {} states, spawn {}, {} steps, board seed {}",
+        settings.grid_w,
+        settings.grid_h,
+        settings.neighborhood.label(),
+        settings.states,
+        settings.spawn_chance,
+        settings.steps,
+        settings.board_seed
+    );
+    println!(
+        "rule_seed\trule\tfinal_density\tmean_density\tdensity_trend\tactivity\ttransient\tperiod\tclass\tbehavior"
+    );
+
+    let seeds = (0..settings.rules).map(|i| settings.seed.wrapping_add(i));
+    #[cfg(feature = "parallel")]
+    let stats: Vec<Stats> = {
+        use rayon::prelude::*;
+        let seeds: Vec<u64> = seeds.collect();
+        seeds
+            .par_iter()
+            .map(|&rule_seed| run_rule(&settings, rule_seed))
+            .collect()
+    };
+    #[cfg(not(feature = "parallel"))]
+    let stats = seeds.map(|rule_seed| run_rule(&settings, rule_seed));
+
+    let cycle_field = |value: Option<usize>| value.map_or("-".to_string(), |v| v.to_string());
+    for stats in stats {
+        println!(
+            "{}\t{}\t{:.4}\t{:.4}\t{:+.4}\t{:.4}\t{}\t{}\t{}\t{}",
+            stats.rule_seed,
+            stats.rule,
+            stats.run.final_density,
+            stats.run.mean_density,
+            stats.run.density_trend,
+            stats.run.activity,
+            cycle_field(stats.run.transient),
+            cycle_field(stats.run.period),
+            stats.behavior.class(),
+            stats.behavior.label()
+        );
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Settings, String> {
+    let mut settings = Settings {
+        rules: 100,
+        steps: 256,
+        grid_w: GRID_W,
+        grid_h: GRID_H,
+        neighborhood: Neighborhood::Rectangle {
+            width: NEIGHBORHOOD_W,
+            height: NEIGHBORHOOD_H,
+        },
+        states: STATES,
+        spawn_chance: SPAWN_CHANCE,
+        seed: ::rand::random(),
+        board_seed: ::rand::random(),
+    };
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let invalid = || format!("invalid value '{}' for {}", value, flag);
+        match flag.as_str() {
+            "--rules" => settings.rules = value.parse().map_err(|_| invalid())?,
+            "--steps" => settings.steps = value.parse().map_err(|_| invalid())?,
+            "--width" => settings.grid_w = value.parse().map_err(|_| invalid())?,
+            "--height" => settings.grid_h = value.parse().map_err(|_| invalid())?,
+            "--neighborhood" => {
+                let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                settings.neighborhood = Neighborhood::Rectangle {
+                    width: width.parse().map_err(|_| invalid())?,
+                    height: height.parse().map_err(|_| invalid())?,
+                };
+            }
+            "--states" => settings.states = value.parse().map_err(|_| invalid())?,
+            "--spawn" => settings.spawn_chance = value.parse().map_err(|_| invalid())?,
+            "--seed" => settings.seed = value.parse().map_err(|_| invalid())?,
+            "--board-seed" => settings.board_seed = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+
+    if settings.grid_w == 0 || settings.grid_h == 0 || settings.steps == 0 {
+        return Err("board size and steps must be at least 1".to_string());
+    }
+    if !(2..=MAX_STATES).contains(&settings.states) {
+        return Err(format!("states must be between 2 and {}", MAX_STATES));
+    }
+    let cells = settings.neighborhood.cell_count();
+    if cells == 0 || cells > max_cells(settings.states) {
+        return Err(format!(
+            "a {} neighborhood must have between 1 and {} cells with {} states",
+            settings.neighborhood.label(),
+            max_cells(settings.states),
+            settings.states
+        ));
+    }
+    if !(0.0..=1.0).contains(&settings.spawn_chance) {
+        return Err("spawn chance must be between 0 and 1".to_string());
+    }
+    Ok(settings)
+}
+
+// Density counts non-zero cells. A cycle is found when a board repeats one seen earlier in
+// the run, which ends the run; `transient` is the step it was first seen at.
+fn run_rule(settings: &Settings, rule_seed: u64) -> Stats {
+    let automata = Automata::with_seeds(
+        settings.grid_w,
+        settings.grid_h,
+        settings.neighborhood.clone(),
+        settings.states,
+        settings.spawn_chance,
+        rule_seed,
+        settings.board_seed,
+    );
+    let rule = if automata.rules().patterns() <= MAX_PRINTED_PATTERNS {
+        automata.rules().wolfram_code().to_string()
+    } else {
+        "-".to_string()
+    };
+    // Classified from the shared board and the seeds after it.
+    let board_seeds: Vec<u64> = (0..ANALYSIS_RUNS as u64)
+        .map(|i| settings.board_seed.wrapping_add(i))
+        .collect();
+    let mut report = analysis::classify(&automata, settings.steps, &board_seeds);
+
+    Stats {
+        rule_seed,
+        rule,
+        run: report.runs.swap_remove(0),
+        behavior: report.behavior,
+    }
+}
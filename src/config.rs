@@ -5,3 +5,13 @@ pub const NEIGHBORHOOD_W: usize = 3;
 pub const NEIGHBORHOOD_H: usize = 1;
 pub const MAX_NEIGHBORHOOD_BITS: usize = 16; // 2^16 = 65_536 combinations
 pub const SPAWN_CHANCE: f32 = 0.2;
+pub const STATES: usize = 2;
+pub const PARALLEL_MIN_CELLS: usize = 256 * 256; // smaller boards step faster on one thread
+pub const MAX_STATES: usize = 16;
+pub const RULE_SEED: Option<u64> = None; // None picks a new seed on every run
+pub const BOARD_SEED: Option<u64> = None;
+pub const STEP_TIME: f32 = 0.05; // seconds between steps
+pub const WINDOW_W: u32 = 800;
+pub const WINDOW_H: u32 = 600;
+pub const ANALYSIS_STEPS: usize = 512; // steps per run when classifying a rule
+pub const ANALYSIS_RUNS: usize = 4; // boards per classification, from consecutive seeds
@@ -1,11 +1,97 @@
+use ::rand::{rngs::SmallRng, SeedableRng};
 use macroquad::prelude::*;
-use macroquad::ui::{hash, root_ui};
+use macroquad::ui::{hash, root_ui, widgets};
 use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 
-use crate::automata::Automata;
-use crate::config::MAX_NEIGHBORHOOD_BITS;
+use cellular_automata::analysis::{self, Report};
+use cellular_automata::automata::{Automata, Boundary};
+use cellular_automata::config::{ANALYSIS_RUNS, ANALYSIS_STEPS, MAX_STATES};
+use cellular_automata::neighborhood::Neighborhood;
+use cellular_automata::pattern::{self, Pattern};
+use cellular_automata::recording::Recording;
+use cellular_automata::rulefile::{parse_rule_file, write_rule_file};
+use cellular_automata::rules::{max_cells, RuleOptions, RulesCollection, Totalism};
+use cellular_automata::rulestring::{parse_generations, GenerationsRule};
+use cellular_automata::session::Session;
+use cellular_automata::snapshot;
+use num_bigint::BigUint;
+
+const NEIGHBORHOOD_SHAPES: [&str; 7] = [
+    "Rectangle (w x h)",
+    "Moore",
+    "von Neumann",
+    "Cross",
+    "Hexagonal",
+    "Circular",
+    "Custom (editor)",
+];
+const EDITOR_RADIUS: isize = 3;
+const RULE_EDITOR_COLUMNS: usize = 4;
+const RULE_EDITOR_ROWS: usize = 8;
+const RULE_ICON_W: f32 = 56.0;
+const RULE_ICON_H: f32 = 40.0;
+const RULE_ICON_GAP: f32 = 8.0;
+// Rule icons draw cells outside the neighborhood in the first color and the column between
+// the pattern and its output in the second.
+const RULE_ICON_COLORS: (Color, Color) = (
+    Color::new(0.30, 0.32, 0.38, 1.0),
+    Color::new(0.10, 0.12, 0.16, 1.0),
+);
+// Zoom is relative to the view that fits the whole texture in the window.
+const ZOOM_STEP: f32 = 1.1;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 512.0;
+// State 0 is always black and state 1 white so binary rules look the same as before.
+const PALETTE: [Color; MAX_STATES] = [
+    BLACK,
+    WHITE,
+    Color::new(0.90, 0.30, 0.25, 1.0),
+    Color::new(0.25, 0.70, 0.35, 1.0),
+    Color::new(0.25, 0.45, 0.90, 1.0),
+    Color::new(0.95, 0.80, 0.20, 1.0),
+    Color::new(0.70, 0.35, 0.85, 1.0),
+    Color::new(0.20, 0.80, 0.80, 1.0),
+    Color::new(0.95, 0.55, 0.15, 1.0),
+    Color::new(0.55, 0.80, 0.20, 1.0),
+    Color::new(0.90, 0.40, 0.65, 1.0),
+    Color::new(0.45, 0.30, 0.20, 1.0),
+    Color::new(0.50, 0.50, 0.55, 1.0),
+    Color::new(0.60, 0.75, 0.95, 1.0),
+    Color::new(0.95, 0.75, 0.60, 1.0),
+    Color::new(0.15, 0.35, 0.30, 1.0),
+];
+// Refractory states of Generations rules fade from the first color to the second as they
+// approach death.
+const REFRACTORY_COLORS: (Color, Color) = (
+    Color::new(0.95, 0.55, 0.15, 1.0),
+    Color::new(0.25, 0.05, 0.10, 1.0),
+);
+
+const GALLERY_COLUMNS: usize = 3;
+const GALLERY_CHILDREN: usize = 9;
+const GALLERY_TILE: f32 = 180.0; // longest side of a child's view, in pixels
+
+// A mutated copy of the current rule, run from the board the gallery was opened on and
+// stepped alongside the main board.
+struct GalleryChild {
+    automata: Automata,
+    history: VecDeque<Vec<u8>>,
+    image: Image,
+    texture: Texture2D,
+}
+
+type AnalysisKey = (
+    String,
+    Option<String>,
+    Vec<(isize, isize)>,
+    (usize, usize),
+    (Boundary, Boundary),
+);
 
-#[derive(Clone)]
 pub struct Game {
     automata: Automata,
     image: Image,
@@ -27,34 +113,130 @@ pub struct Game {
     show_history: bool,
     spawn_chance: f32,
     input_spawn_chance: String,
+    states: usize,
+    input_states: String,
+    rule_seed: u64,
+    board_seed: u64,
+    input_rule_seed: String,
+    input_board_seed: String,
+    rule_number: String,
+    input_rule_number: String,
+    rule_error: Option<String>,
+    rulestring: Option<String>,
+    input_rulestring: String,
+    boundary_x: Boundary,
+    boundary_y: Boundary,
+    neighborhood: Neighborhood,
+    neighborhood_shape: usize,
+    input_neighborhood_radius: String,
+    show_neighborhood_editor: bool,
+    editor_offsets: Vec<(isize, isize)>,
+    paint_state: u8,
+    last_paint: Option<(usize, usize)>,
+    zoom: f32,
+    pan: Vec2,
+    last_pan_position: Option<Vec2>,
+    show_rule_editor: bool,
+    show_gallery: bool,
+    use_lambda: bool,
+    lambda: f32,
+    exact_lambda: bool,
+    quiescent: bool,
+    mirror_x: bool,
+    mirror_y: bool,
+    rotate: bool,
+    totalism: Totalism,
+    gallery: Vec<GalleryChild>,
+    gallery_mate: Option<usize>,
+    input_mutation_rate: String,
+    rule_page: usize,
+    input_rule_page: String,
+    rule_icons: Vec<(Texture2D, Vec2)>,
+    // The page, neighborhood, state count and outputs the icons were drawn for.
+    rule_icons_key: (usize, Vec<(isize, isize)>, usize, Vec<u8>),
+    analysis: Option<Report>,
+    // The rule, rulestring, neighborhood, board size and edges the report was made for.
+    analysis_key: AnalysisKey,
+    // A classification running on its own thread, and the key it was started for.
+    classifying: Option<(AnalysisKey, Receiver<Report>)>,
+    input_session_path: String,
+    file_status: Option<String>,
+    input_pattern_path: String,
+    input_pattern_x: String,
+    input_pattern_y: String,
+    input_rule_path: String,
+    input_png_path: String,
+    input_png_scale: String,
+    recording: Option<Recording>,
+    recording_history: bool,
+    input_record_path: String,
+    input_record_delay: String,
+    input_record_scale: String,
+    input_record_max: String,
+    // Board cells as (x, y, width, height), chosen with ctrl + left drag.
+    selection: Option<(usize, usize, usize, usize)>,
+    selection_start: Option<(usize, usize)>,
+}
+
+pub fn state_color(state: u8, generations: Option<&GenerationsRule>) -> Color {
+    match generations {
+        Some(rule) if state >= 2 => {
+            let t = (state - 2) as f32 / (rule.states() - 2).max(1) as f32;
+            let (young, old) = REFRACTORY_COLORS;
+            Color::new(
+                young.r + (old.r - young.r) * t,
+                young.g + (old.g - young.g) * t,
+                young.b + (old.b - young.b) * t,
+                1.0,
+            )
+        }
+        _ => PALETTE[state as usize % PALETTE.len()],
+    }
+}
+
+pub fn color_bytes(color: Color) -> [u8; 4] {
+    [color.r, color.g, color.b, color.a].map(|channel| (channel * 255.0).round() as u8)
 }
 
 impl Game {
+    // Seeds left as `None` are picked at random.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         grid_w: usize,
         grid_h: usize,
         history_length: usize,
         neighborhood_w: usize,
         neighborhood_h: usize,
+        states: usize,
         spawn_chance: f32,
+        rule_seed: Option<u64>,
+        board_seed: Option<u64>,
+        step_time: f32,
     ) -> Self {
+        let rule_seed = rule_seed.unwrap_or_else(::rand::random);
+        let board_seed = board_seed.unwrap_or_else(::rand::random);
         let image = Image::gen_image_color(grid_w as u16, (grid_h * history_length) as u16, BLACK);
         let texture = Texture2D::from_image(&image);
         texture.set_filter(FilterMode::Nearest);
 
         let mut game = Self {
-            automata: Automata::new(
+            automata: Automata::with_seeds(
                 grid_w,
                 grid_h,
-                neighborhood_w,
-                neighborhood_h,
+                Neighborhood::Rectangle {
+                    width: neighborhood_w,
+                    height: neighborhood_h,
+                },
+                states,
                 spawn_chance,
+                rule_seed,
+                board_seed,
             ),
             image,
             texture,
             paused: false,
             time_since_last_step: 0.0f32,
-            step_time: 0.05f32,
+            step_time,
             cells_history: VecDeque::with_capacity(history_length),
             history_length,
             grid_w,
@@ -69,6 +251,74 @@ impl Game {
             show_history: true,
             spawn_chance,
             input_spawn_chance: format!("{:.2}", spawn_chance),
+            states,
+            input_states: states.to_string(),
+            rule_seed,
+            board_seed,
+            input_rule_seed: rule_seed.to_string(),
+            input_board_seed: board_seed.to_string(),
+            rule_number: String::new(),
+            input_rule_number: String::new(),
+            rule_error: None,
+            rulestring: None,
+            input_rulestring: "B3/S23".to_string(),
+            boundary_x: Boundary::Periodic,
+            boundary_y: Boundary::Periodic,
+            neighborhood: Neighborhood::Rectangle {
+                width: neighborhood_w,
+                height: neighborhood_h,
+            },
+            neighborhood_shape: 0,
+            input_neighborhood_radius: "1".to_string(),
+            show_neighborhood_editor: false,
+            editor_offsets: Vec::new(),
+            paint_state: 1,
+            last_paint: None,
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+            last_pan_position: None,
+            show_rule_editor: false,
+            show_gallery: false,
+            use_lambda: false,
+            lambda: 0.5,
+            exact_lambda: false,
+            quiescent: false,
+            mirror_x: false,
+            mirror_y: false,
+            rotate: false,
+            totalism: Totalism::Off,
+            gallery: Vec::new(),
+            gallery_mate: None,
+            input_mutation_rate: "0.05".to_string(),
+            rule_page: 0,
+            input_rule_page: "1".to_string(),
+            rule_icons: Vec::new(),
+            rule_icons_key: (0, Vec::new(), 0, Vec::new()),
+            analysis: None,
+            analysis_key: (
+                String::new(),
+                None,
+                Vec::new(),
+                (0, 0),
+                (Boundary::Periodic, Boundary::Periodic),
+            ),
+            classifying: None,
+            input_session_path: "session.json".to_string(),
+            file_status: None,
+            input_pattern_path: "pattern.rle".to_string(),
+            input_pattern_x: "0".to_string(),
+            input_pattern_y: "0".to_string(),
+            input_rule_path: "rule.rule".to_string(),
+            input_png_path: "spacetime.png".to_string(),
+            input_png_scale: "4".to_string(),
+            recording: None,
+            recording_history: false,
+            input_record_path: "run.gif".to_string(),
+            input_record_delay: "100".to_string(),
+            input_record_scale: "4".to_string(),
+            input_record_max: "500".to_string(),
+            selection: None,
+            selection_start: None,
         };
         game.init();
         game
@@ -86,16 +336,296 @@ impl Game {
             .map(|_| vec![0u8; self.grid_w * self.grid_h])
             .collect();
         self.add_history();
+        self.refresh_rule_number();
+    }
+
+    fn analysis_key(&self) -> AnalysisKey {
+        (
+            self.rule_number.clone(),
+            self.rulestring.clone(),
+            self.automata.neighborhood().offsets(),
+            self.automata.size(),
+            self.automata.boundary(),
+        )
+    }
+
+    // Runs copies of the current rule from the board seed and the seeds after it; the board
+    // on screen is left alone. Large boards take seconds, so the runs go on their own thread
+    // and `poll_analysis` picks up the report.
+    fn classify_rule(&mut self) {
+        if self.classifying.is_some() {
+            return;
+        }
+        let seeds: Vec<u64> = (0..ANALYSIS_RUNS as u64)
+            .map(|i| self.board_seed.wrapping_add(i))
+            .collect();
+        let automata = self.automata.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(analysis::classify(&automata, ANALYSIS_STEPS, &seeds));
+        });
+        self.classifying = Some((self.analysis_key(), receiver));
+    }
+
+    fn poll_analysis(&mut self) {
+        let Some((key, receiver)) = self.classifying.take() else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(report) => {
+                self.analysis = Some(report);
+                self.analysis_key = key;
+            }
+            Err(TryRecvError::Empty) => self.classifying = Some((key, receiver)),
+            // The thread panicked; the A key can start another.
+            Err(TryRecvError::Disconnected) => {}
+        }
+    }
+
+    // The last report, unless the rule or board has changed since.
+    fn current_analysis(&self) -> Option<&Report> {
+        self.analysis
+            .as_ref()
+            .filter(|_| self.analysis_key == self.analysis_key())
+    }
+
+    fn refresh_rule_number(&mut self) {
+        self.rule_number = self.automata.rules().wolfram_code().to_string();
+    }
+
+    fn apply_rule_number(&mut self) {
+        let code = match self.input_rule_number.trim().parse::<BigUint>() {
+            Ok(code) => code,
+            Err(_) => {
+                self.rule_error = Some("Rule number must be a non-negative integer".to_string());
+                return;
+            }
+        };
+        let mut rules = self.automata.rules().clone();
+        match rules.set_wolfram_code(&code) {
+            Ok(()) => {
+                self.automata.set_rules(rules);
+                self.rule_error = None;
+                self.rulestring = None;
+                self.refresh_rule_number();
+            }
+            Err(err) => self.rule_error = Some(err),
+        }
+    }
+
+    fn apply_rulestring(&mut self) {
+        let rule = match parse_generations(&self.input_rulestring) {
+            Ok(rule) => rule,
+            Err(err) => {
+                self.rule_error = Some(err);
+                return;
+            }
+        };
+        match rule.as_life_like() {
+            Some(life) => self.load_rules(RulesCollection::from_life_like(life)),
+            // Generations rules run outside the rule table, which stays as it was; the states
+            // input shows the running count so a rebuild keeps the rule.
+            None => {
+                self.input_states = rule.states().to_string();
+                self.automata.set_generations(rule);
+            }
+        }
+        self.rule_error = None;
+        self.rulestring = Some(self.input_rulestring.trim().to_string());
+    }
+
+    // Switches to a whole new rule table, taking its neighborhood and state count with it.
+    fn load_rules(&mut self, rules: RulesCollection) {
+        self.neighborhood = rules.neighborhood().clone();
+        self.states = rules.states();
+        self.input_states = self.states.to_string();
+        self.automata.set_rules(rules);
+        self.sync_neighborhood_inputs();
+        self.refresh_rule_number();
+    }
+
+    fn import_rule_file(&mut self) {
+        let path = self.input_rule_path.trim().to_string();
+        let rules = fs::read_to_string(&path)
+            .map_err(|err| format!("could not read {}: {}", path, err))
+            .and_then(|text| parse_rule_file(&text).map_err(|err| format!("{}: {}", path, err)));
+        self.file_status = Some(match rules {
+            Ok(rules) => {
+                let status = format!(
+                    "Imported {} states, {} from {}",
+                    rules.states(),
+                    rules.neighborhood().label(),
+                    path
+                );
+                self.load_rules(rules);
+                self.rulestring = None;
+                self.rule_error = None;
+                status
+            }
+            Err(err) => err,
+        });
+    }
+
+    // The rule is named after the file, as Golly expects.
+    fn export_rule_file(&mut self) {
+        // The table under a Generations rule is not what is running.
+        if self.automata.generations().is_some() {
+            self.file_status =
+                Some("Generations rules cannot be exported as a rule table".to_string());
+            return;
+        }
+        let path = self.input_rule_path.trim().to_string();
+        let name = Path::new(&path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("Exported");
+        let written = write_rule_file(self.automata.rules(), name).and_then(|text| {
+            fs::write(&path, text).map_err(|err| format!("could not write {}: {}", path, err))
+        });
+        self.file_status = Some(match written {
+            Ok(()) => format!("Exported rule table to {}", path),
+            Err(err) => err,
+        });
     }
 
+    fn save_session(&mut self) {
+        let earlier: Vec<Vec<u8>> = self
+            .cells_history
+            .iter()
+            .take(self.cells_history.len().saturating_sub(1))
+            .cloned()
+            .collect();
+        let session = Session::capture(
+            &self.automata,
+            self.rulestring.clone(),
+            &earlier,
+            self.history_length,
+            self.step_time,
+        );
+        let path = self.input_session_path.trim();
+        self.file_status = Some(match session.save(path) {
+            Ok(()) => format!("Saved {}", path),
+            Err(err) => err,
+        });
+    }
+
+    fn load_session(&mut self) {
+        let path = self.input_session_path.trim().to_string();
+        self.file_status = Some(
+            match Session::load(&path).and_then(|session| self.apply_session(session)) {
+                Ok(()) => format!("Loaded {}", path),
+                Err(err) => err,
+            },
+        );
+    }
+
+    // Everything that can fail is decoded before the game is touched.
+    fn apply_session(&mut self, session: Session) -> Result<(), String> {
+        let rules = session.rules()?;
+        let generations = session.generations_rule()?;
+        let board = session.board()?;
+        let history = session.history()?;
+
+        let options = &session.rule_options;
+        self.use_lambda = options.lambda.is_some();
+        self.lambda = options.lambda.map_or(self.lambda, |lambda| lambda as f32);
+        self.exact_lambda = options.exact_lambda;
+        self.quiescent = options.quiescent;
+        self.mirror_x = options.mirror_x;
+        self.mirror_y = options.mirror_y;
+        self.rotate = options.rotate;
+        self.totalism = options.totalism;
+        self.rule_seed = session.rule_seed;
+        self.board_seed = session.board_seed;
+        self.boundary_x = session.boundary_x;
+        self.boundary_y = session.boundary_y;
+        self.step_time = session.step_time.clamp(0.001, 5.0);
+        self.rebuild(
+            session.grid_w,
+            session.grid_h,
+            session.history_length,
+            session.neighborhood,
+            session.states,
+            session.spawn_chance,
+        );
+        self.automata.set_rules(rules);
+        if let Some(rule) = generations {
+            self.automata.set_generations(rule);
+        }
+        self.automata.set_cells(&board)?;
+        self.rulestring = session.generations;
+        self.input_states = self.automata.states().to_string();
+        self.cells_history = history.into_iter().collect();
+        while self.cells_history.len() + 1 < self.history_length {
+            self.cells_history
+                .push_front(vec![0u8; self.grid_w * self.grid_h]);
+        }
+        self.cells_history.push_back(board);
+        self.refresh_rule_number();
+        Ok(())
+    }
+
+    // A rule in the pattern is loaded first when the rulestring parser understands it.
+    fn import_pattern(&mut self) {
+        let path = self.input_pattern_path.trim().to_string();
+        let pattern = match pattern::load(&path) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                self.file_status = Some(err);
+                return;
+            }
+        };
+        let mut status = format!(
+            "Imported {}x{} from {}",
+            pattern.width, pattern.height, path
+        );
+        if let Some(rule) = &pattern.rule {
+            if parse_generations(rule).is_ok() {
+                self.input_rulestring = rule.clone();
+                self.apply_rulestring();
+            } else {
+                status = format!("{} (rule {} not loaded)", status, rule);
+            }
+        }
+        let x = self.input_pattern_x.trim().parse().unwrap_or(0);
+        let y = self.input_pattern_y.trim().parse().unwrap_or(0);
+        pattern.place(&mut self.automata, x, y);
+        self.refresh_current_history();
+        self.file_status = Some(status);
+    }
+
+    // Exports the selection if there is one, otherwise the whole board.
+    fn export_pattern(&mut self) {
+        let (x, y, width, height) = self.selection.unwrap_or((0, 0, self.grid_w, self.grid_h));
+        let mut pattern = Pattern::from_board(&self.automata, x, y, width, height);
+        pattern.rule = self.rulestring.clone();
+        let path = self.input_pattern_path.trim();
+        self.file_status = Some(match pattern::save(path, &pattern) {
+            Ok(()) => format!("Exported {}x{} to {}", pattern.width, pattern.height, path),
+            Err(err) => err,
+        });
+    }
+
+    // Starts over with fresh seeds for both the rule table and the board.
     fn reset(&mut self) {
-        self.automata.randomize_rules();
-        self.automata.randomize();
+        self.rule_seed = ::rand::random();
+        self.board_seed = ::rand::random();
+        self.restart();
+    }
+
+    // Draws the rule table and the board again from the current seeds.
+    fn restart(&mut self) {
+        self.automata.reseed_rules(self.rule_seed);
+        self.automata.reseed_board(self.board_seed);
+        self.input_rule_seed = self.rule_seed.to_string();
+        self.input_board_seed = self.board_seed.to_string();
         self.cells_history = (0..self.history_length)
             .map(|_| vec![0u8; self.grid_w * self.grid_h])
             .collect();
         self.add_history();
         self.time_since_last_step = 0.0;
+        self.rulestring = None;
+        self.refresh_rule_number();
     }
 
     fn update_texture(&mut self) {
@@ -104,7 +634,7 @@ impl Game {
                 for x in 0..self.grid_w {
                     let idx = y * self.grid_w + x;
                     let c = history[idx];
-                    let color = if c == 1 { WHITE } else { BLACK };
+                    let color = self.state_color(c);
                     self.image
                         .set_pixel(x as u32, (y + row_idx * self.grid_h) as u32, color);
                 }
@@ -114,23 +644,167 @@ impl Game {
         self.texture.update(&self.image);
     }
 
-    fn clamp_neighborhood_to_limit(&self, mut w: usize, mut h: usize) -> (usize, usize) {
-        let max_bits: usize = MAX_NEIGHBORHOOD_BITS;
-        w = w.max(1);
-        h = h.max(1);
-        if w * h <= max_bits {
-            return (w, h);
+    fn state_color(&self, state: u8) -> Color {
+        state_color(state, self.automata.generations())
+    }
+
+    // Texture rows from `first_row` on, as RGBA bytes.
+    fn image_rows(&self, first_row: usize, rows: usize) -> &[u8] {
+        let row_bytes = self.grid_w * 4;
+        &self.image.bytes[first_row * row_bytes..(first_row + rows) * row_bytes]
+    }
+
+    // Writes the rows on screen: the whole history so far, or just the current board.
+    fn save_screenshot(&mut self) {
+        self.update_texture();
+        let scale = self.input_png_scale.trim().parse().unwrap_or(0);
+        let (first_row, rows) = if self.show_history {
+            (0, self.cells_history.len() * self.grid_h)
+        } else {
+            (self.current_board_row(), self.grid_h)
+        };
+        let rgba = self.image_rows(first_row, rows);
+        let path = self.input_png_path.trim();
+        self.file_status = Some(
+            match snapshot::write_png(path, self.grid_w, rows, rgba, scale) {
+                Ok(()) => format!(
+                    "Saved {}x{} image to {}",
+                    self.grid_w * scale,
+                    rows * scale,
+                    path
+                ),
+                Err(err) => err,
+            },
+        );
+    }
+
+    // Frames keep the view that was on screen when recording started: the full history
+    // window, or the current board only.
+    fn toggle_recording(&mut self) {
+        if self.recording.is_some() {
+            self.finish_recording();
+            return;
         }
-        while w * h > max_bits {
-            if w >= h && w > 1 {
-                w -= 1;
-            } else if h > 1 {
-                h -= 1;
-            } else {
-                break;
+        let rows = if self.show_history {
+            self.grid_h * self.history_length
+        } else {
+            self.grid_h
+        };
+        let Ok(delay_ms) = self.input_record_delay.trim().parse::<u16>() else {
+            self.file_status = Some(format!("Delay must be 0-{} ms", u16::MAX));
+            return;
+        };
+        let recording = Recording::start(
+            self.input_record_path.trim(),
+            self.grid_w,
+            rows,
+            self.input_record_scale.trim().parse().unwrap_or(0),
+            delay_ms,
+            self.input_record_max.trim().parse().unwrap_or(0),
+        );
+        match recording {
+            Ok(recording) => {
+                self.recording = Some(recording);
+                self.recording_history = self.show_history;
+                self.file_status = Some(format!("Recording to {}", self.input_record_path.trim()));
+                self.record_frame();
+            }
+            Err(err) => self.file_status = Some(err),
+        }
+    }
+
+    fn record_frame(&mut self) {
+        if self.recording.is_none() {
+            return;
+        }
+        self.update_texture();
+        let (first_row, rows) = if self.recording_history {
+            (0, self.grid_h * self.history_length)
+        } else {
+            (self.current_board_row(), self.grid_h)
+        };
+        let rgba = self.image_rows(first_row, rows).to_vec();
+        let Some(recording) = self.recording.as_mut() else {
+            return;
+        };
+        match recording.add_frame(&rgba) {
+            Ok(()) if recording.is_full() => self.finish_recording(),
+            Ok(()) => {}
+            Err(err) => {
+                self.recording = None;
+                self.file_status = Some(err);
+            }
+        }
+    }
+
+    // Also called when the window closes, so a recording in progress is never left
+    // half-written.
+    pub fn finish_recording(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+        let path = self.input_record_path.trim().to_string();
+        self.file_status = Some(match recording.finish() {
+            Ok(frames) => format!("Recorded {} generations to {}", frames, path),
+            Err(err) => err,
+        });
+    }
+
+    fn clamp_neighborhood_to_limit(&self, neighborhood: Neighborhood) -> Neighborhood {
+        let max_bits: usize = max_cells(self.states);
+        match neighborhood {
+            Neighborhood::Rectangle {
+                width: mut w,
+                height: mut h,
+            } => {
+                w = w.max(1);
+                h = h.max(1);
+                while w * h > max_bits {
+                    if w >= h && w > 1 {
+                        w -= 1;
+                    } else if h > 1 {
+                        h -= 1;
+                    } else {
+                        break;
+                    }
+                }
+                Neighborhood::Rectangle {
+                    width: w,
+                    height: h,
+                }
+            }
+            Neighborhood::Custom(_) if neighborhood.cell_count() > max_bits => {
+                self.neighborhood.clone()
+            }
+            other => {
+                let mut radius = other.radius().unwrap_or(0);
+                while radius > 0 && other.with_radius(radius).cell_count() > max_bits {
+                    radius -= 1;
+                }
+                other.with_radius(radius)
+            }
+        }
+    }
+
+    fn sync_neighborhood_inputs(&mut self) {
+        self.neighborhood_shape = match &self.neighborhood {
+            Neighborhood::Rectangle { width, height } => {
+                self.neighborhood_w = *width;
+                self.neighborhood_h = *height;
+                0
             }
+            Neighborhood::Moore(_) => 1,
+            Neighborhood::VonNeumann(_) => 2,
+            Neighborhood::Cross(_) => 3,
+            Neighborhood::Hexagonal => 4,
+            Neighborhood::Circular(_) => 5,
+            Neighborhood::Custom(_) => 6,
+        };
+        if let Some(radius) = self.neighborhood.radius() {
+            self.input_neighborhood_radius = radius.to_string();
         }
-        (w, h)
+        self.input_neighborhood_w = self.neighborhood_w.to_string();
+        self.input_neighborhood_h = self.neighborhood_h.to_string();
     }
 
     fn rebuild(
@@ -138,26 +812,32 @@ impl Game {
         grid_w: usize,
         grid_h: usize,
         history_length: usize,
-        neighborhood_w: usize,
-        neighborhood_h: usize,
+        neighborhood: Neighborhood,
+        states: usize,
         spawn_chance: f32,
     ) {
+        // Frames must all be the same size, and children must match the board.
+        self.finish_recording();
+        self.show_gallery = false;
+        self.gallery.clear();
         self.grid_w = grid_w.max(1);
         self.grid_h = grid_h.max(1);
         self.history_length = history_length.max(1);
-        let (neighborhood_w, neighborhood_h) =
-            self.clamp_neighborhood_to_limit(neighborhood_w, neighborhood_h);
-        self.neighborhood_w = neighborhood_w;
-        self.neighborhood_h = neighborhood_h;
+        self.states = states.clamp(2, MAX_STATES);
+        self.neighborhood = self.clamp_neighborhood_to_limit(neighborhood);
         self.spawn_chance = spawn_chance.clamp(0.0, 1.0);
 
-        self.automata = Automata::new(
+        self.automata = Automata::with_seeds(
             self.grid_w,
             self.grid_h,
-            neighborhood_w,
-            neighborhood_h,
+            self.neighborhood.clone(),
+            self.states,
             self.spawn_chance,
+            self.rule_seed,
+            self.board_seed,
         );
+        self.automata.set_boundary(self.boundary_x, self.boundary_y);
+        self.automata.set_rule_options(self.rule_options());
 
         self.image = Image::gen_image_color(
             self.grid_w as u16,
@@ -167,14 +847,16 @@ impl Game {
         self.texture = Texture2D::from_image(&self.image);
         self.texture.set_filter(FilterMode::Nearest);
 
-        self.reset();
+        self.fit_view();
+        self.selection = None;
+        self.restart();
 
         self.input_grid_w = self.grid_w.to_string();
         self.input_grid_h = self.grid_h.to_string();
-        self.input_neighborhood_w = self.neighborhood_w.to_string();
-        self.input_neighborhood_h = self.neighborhood_h.to_string();
+        self.sync_neighborhood_inputs();
         self.input_history_length = self.history_length.to_string();
         self.input_spawn_chance = format!("{:.2}", self.spawn_chance);
+        self.input_states = self.states.to_string();
     }
 
     fn apply_inputs(&mut self) {
@@ -197,70 +879,772 @@ impl Game {
         let new_h = parse(&self.input_grid_h, self.grid_h);
         let new_neighborhood_w = parse(&self.input_neighborhood_w, self.neighborhood_w);
         let new_neighborhood_h = parse(&self.input_neighborhood_h, self.neighborhood_h);
+        let new_radius = self
+            .input_neighborhood_radius
+            .trim()
+            .parse::<usize>()
+            .unwrap_or(self.neighborhood.radius().unwrap_or(1));
+        let new_neighborhood = match self.neighborhood_shape {
+            0 => Neighborhood::Rectangle {
+                width: new_neighborhood_w,
+                height: new_neighborhood_h,
+            },
+            1 => Neighborhood::Moore(new_radius),
+            2 => Neighborhood::VonNeumann(new_radius),
+            3 => Neighborhood::Cross(new_radius),
+            4 => Neighborhood::Hexagonal,
+            5 => Neighborhood::Circular(new_radius),
+            _ => Neighborhood::custom(self.editor_offsets.iter().copied())
+                .unwrap_or_else(|_| self.neighborhood.clone()),
+        };
         let new_history_length = parse(&self.input_history_length, self.history_length);
         let new_spawn_chance = parse_f32(&self.input_spawn_chance, self.spawn_chance);
+        let new_states = parse(&self.input_states, self.states);
+        // A Generations rule outlives the rebuild unless the state count was changed away from
+        // its own; the table under it keeps its state count.
+        let generations = self
+            .automata
+            .generations()
+            .filter(|rule| rule.states() == new_states)
+            .cloned()
+            .zip(self.rulestring.clone());
+        let new_states = if generations.is_some() {
+            self.states
+        } else {
+            new_states
+        };
+        self.rule_seed = self
+            .input_rule_seed
+            .trim()
+            .parse()
+            .unwrap_or(self.rule_seed);
+        self.board_seed = self
+            .input_board_seed
+            .trim()
+            .parse()
+            .unwrap_or(self.board_seed);
 
         self.rebuild(
             new_w,
             new_h,
             new_history_length,
-            new_neighborhood_w,
-            new_neighborhood_h,
+            new_neighborhood,
+            new_states,
             new_spawn_chance,
         );
+        if let Some((rule, rulestring)) = generations {
+            self.input_states = rule.states().to_string();
+            self.automata.set_generations(rule);
+            // Reseeded so new cells start alive rather than refractory.
+            self.automata.reseed_board(self.board_seed);
+            self.refresh_current_history();
+            self.rulestring = Some(rulestring);
+        }
     }
 
     fn draw_ui(&mut self) {
         let padding_y = 36.0;
         let width = 260.0;
+        let texts = self.input_texts();
         root_ui().window(
             hash!("controls"),
             vec2(12.0, padding_y),
-            vec2(width, 280.0),
+            vec2(width, 1210.0),
             |ui| {
                 ui.label(None, "Board width");
                 ui.input_text(hash!("grid_w"), "", &mut self.input_grid_w);
                 ui.label(None, "Board height");
                 ui.input_text(hash!("grid_h"), "", &mut self.input_grid_h);
-                ui.label(None, "Neighborhood width");
+                ui.label(
+                    None,
+                    &format!(
+                        "Neighborhood: {} ({} cells)",
+                        self.neighborhood.label(),
+                        self.neighborhood.cell_count()
+                    ),
+                );
+                ui.combo_box(
+                    hash!("nb_shape"),
+                    "Shape",
+                    &NEIGHBORHOOD_SHAPES,
+                    &mut self.neighborhood_shape,
+                );
+                ui.label(None, "Neighborhood width / height (rectangle)");
                 ui.input_text(hash!("nb_w"), "", &mut self.input_neighborhood_w);
-                ui.label(None, "Neighborhood height");
                 ui.input_text(hash!("nb_h"), "", &mut self.input_neighborhood_h);
+                ui.label(None, "Neighborhood radius");
+                ui.input_text(hash!("nb_radius"), "", &mut self.input_neighborhood_radius);
                 ui.label(None, "History length");
                 ui.input_text(hash!("hist"), "", &mut self.input_history_length);
                 ui.label(None, "Spawn chance (0-1)");
                 ui.input_text(hash!("spawn"), "", &mut self.input_spawn_chance);
+                ui.label(None, "States (2-16)");
+                ui.input_text(hash!("states"), "", &mut self.input_states);
+                ui.label(None, "Rule seed");
+                ui.input_text(hash!("rule_seed"), "", &mut self.input_rule_seed);
+                ui.label(None, "Board seed");
+                ui.input_text(hash!("board_seed"), "", &mut self.input_board_seed);
+                ui.checkbox(hash!("use_lambda"), "Lambda", &mut self.use_lambda);
+                ui.same_line(0.0);
+                ui.checkbox(hash!("exact_lambda"), "Exact", &mut self.exact_lambda);
+                ui.same_line(0.0);
+                ui.checkbox(hash!("quiescent"), "Quiescent", &mut self.quiescent);
+                ui.slider(hash!("lambda"), "lambda", 0.0..1.0, &mut self.lambda);
+                ui.checkbox(hash!("mirror_x"), "Mirror L-R", &mut self.mirror_x);
+                ui.same_line(0.0);
+                ui.checkbox(hash!("mirror_y"), "Mirror U-D", &mut self.mirror_y);
+                ui.same_line(0.0);
+                ui.checkbox(hash!("rotate"), "Rotate", &mut self.rotate);
+                let labels: Vec<&str> = Totalism::ALL.iter().map(Totalism::label).collect();
+                let mut totalism = Totalism::ALL
+                    .iter()
+                    .position(|&totalism| totalism == self.totalism)
+                    .unwrap_or(0);
+                ui.combo_box(hash!("totalism"), "Sums", &labels, &mut totalism);
+                self.totalism = Totalism::ALL[totalism];
 
                 if ui.button(None, "Apply (rebuild)") {
                     self.apply_inputs();
                 }
+
+                ui.separator();
+                let labels = Boundary::ALL.map(Boundary::label);
+                let mut boundary_x = Boundary::ALL
+                    .iter()
+                    .position(|b| *b == self.boundary_x)
+                    .unwrap_or(0);
+                let mut boundary_y = Boundary::ALL
+                    .iter()
+                    .position(|b| *b == self.boundary_y)
+                    .unwrap_or(0);
+                ui.combo_box(hash!("boundary_x"), "X edges", &labels, &mut boundary_x);
+                ui.combo_box(hash!("boundary_y"), "Y edges", &labels, &mut boundary_y);
+                if Boundary::ALL[boundary_x] != self.boundary_x
+                    || Boundary::ALL[boundary_y] != self.boundary_y
+                {
+                    self.boundary_x = Boundary::ALL[boundary_x];
+                    self.boundary_y = Boundary::ALL[boundary_y];
+                    self.automata.set_boundary(self.boundary_x, self.boundary_y);
+                }
+
+                ui.separator();
+                let max_digits = 24;
+                if self.rule_number.len() > max_digits {
+                    ui.label(
+                        None,
+                        &format!(
+                            "Rule: {}... ({} digits)",
+                            &self.rule_number[..max_digits],
+                            self.rule_number.len()
+                        ),
+                    );
+                } else {
+                    ui.label(None, &format!("Rule: {}", self.rule_number));
+                }
+                ui.label(None, "Rule number (Wolfram code)");
+                ui.input_text(hash!("rule_number"), "", &mut self.input_rule_number);
+                if ui.button(None, "Set rule") {
+                    self.apply_rule_number();
+                }
+                ui.label(None, "Rulestring (e.g. B3/S23, B36/S23, B2/S/C3)");
+                ui.input_text(hash!("rulestring"), "", &mut self.input_rulestring);
+                if ui.button(None, "Load rulestring (3x3)") {
+                    self.apply_rulestring();
+                }
+                match (&self.rulestring, self.automata.generations()) {
+                    (Some(rulestring), Some(rule)) => ui.label(
+                        None,
+                        &format!("Running {} ({} states)", rulestring, rule.states()),
+                    ),
+                    (Some(rulestring), None) => ui.label(None, &format!("Running {}", rulestring)),
+                    _ => {}
+                }
+                if let Some(err) = &self.rule_error {
+                    ui.label(None, err);
+                }
+                if ui.button(None, "Classify rule (A)") {
+                    self.classify_rule();
+                }
+                if self.classifying.is_some() {
+                    ui.label(None, "Classifying...");
+                } else if let Some(report) = self.current_analysis() {
+                    let runs = report.runs.len() as f64;
+                    ui.label(
+                        None,
+                        &format!(
+                            "Class {} ({}): entropy {:.2}, damage {:.2}",
+                            report.behavior.class(),
+                            report.behavior.label(),
+                            report.runs.iter().map(|run| run.entropy).sum::<f64>() / runs,
+                            report.runs.iter().map(|run| run.damage).sum::<f64>() / runs
+                        ),
+                    );
+                }
+
+                ui.separator();
+                ui.label(None, "Session file (F5 save, F9 load)");
+                ui.input_text(hash!("session_path"), "", &mut self.input_session_path);
+                if ui.button(None, "Save session") {
+                    self.save_session();
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Load session") {
+                    self.load_session();
+                }
+                ui.label(None, "Pattern file (.rle, .cells, .mc) and x / y");
+                ui.input_text(hash!("pattern_path"), "", &mut self.input_pattern_path);
+                ui.input_text(hash!("pattern_x"), "x", &mut self.input_pattern_x);
+                ui.input_text(hash!("pattern_y"), "y", &mut self.input_pattern_y);
+                if ui.button(None, "Import pattern") {
+                    self.import_pattern();
+                }
+                ui.same_line(0.0);
+                let export_label = if self.selection.is_some() {
+                    "Export selection"
+                } else {
+                    "Export board"
+                };
+                if ui.button(None, export_label) {
+                    self.export_pattern();
+                }
+                ui.label(None, "Golly rule file (.rule)");
+                ui.input_text(hash!("rule_path"), "", &mut self.input_rule_path);
+                if ui.button(None, "Import rule") {
+                    self.import_rule_file();
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "Export rule") {
+                    self.export_rule_file();
+                }
+                ui.label(None, "PNG file and scale (P saves the view)");
+                ui.input_text(hash!("png_path"), "", &mut self.input_png_path);
+                ui.input_text(hash!("png_scale"), "scale", &mut self.input_png_scale);
+                if ui.button(None, "Save PNG") {
+                    self.save_screenshot();
+                }
+                ui.label(None, "Recording (.gif / .apng), G toggles");
+                ui.input_text(hash!("record_path"), "", &mut self.input_record_path);
+                ui.input_text(
+                    hash!("record_delay"),
+                    "delay ms",
+                    &mut self.input_record_delay,
+                );
+                ui.input_text(hash!("record_scale"), "scale", &mut self.input_record_scale);
+                ui.input_text(
+                    hash!("record_max"),
+                    "max frames",
+                    &mut self.input_record_max,
+                );
+                let record_label = if self.recording.is_some() {
+                    "Stop recording"
+                } else {
+                    "Record"
+                };
+                if ui.button(None, record_label) {
+                    self.toggle_recording();
+                }
+                if let Some(status) = &self.file_status {
+                    ui.label(None, status);
+                }
             },
         );
+        if self.show_neighborhood_editor {
+            self.draw_neighborhood_editor(12.0 + width + 12.0, padding_y);
+        }
+        if self.show_rule_editor {
+            self.draw_rule_editor(padding_y);
+        }
+        if self.show_gallery {
+            self.draw_gallery();
+        }
+        // Keys typed into a text box (a path, or a rulestring such as B2/S/C3) are not shortcuts.
+        let typing = self.input_texts() != texts;
         self.sanitize_inputs();
+        self.sync_rule_options();
+        if !typing {
+            self.handle_keys();
+        }
+    }
+
+    fn rule_options(&self) -> RuleOptions {
+        RuleOptions {
+            lambda: self.use_lambda.then_some(self.lambda as f64),
+            exact_lambda: self.exact_lambda,
+            quiescent: self.quiescent,
+            mirror_x: self.mirror_x,
+            mirror_y: self.mirror_y,
+            rotate: self.rotate,
+            totalism: self.totalism,
+        }
+    }
+
+    // Moving the lambda controls redraws the table from the same rule seed, so the effect of
+    // lambda alone can be seen.
+    fn sync_rule_options(&mut self) {
+        let options = self.rule_options();
+        if *self.automata.rule_options() == options {
+            return;
+        }
+        self.rule_error = self.automata.rules().check_symmetry(&options).err();
+        self.automata.set_rule_options(options);
+        self.automata.reseed_rules(self.rule_seed);
+        self.rulestring = None;
+        self.refresh_rule_number();
+    }
+
+    fn toggle_gallery(&mut self) {
+        self.show_gallery = !self.show_gallery;
+        if self.show_gallery {
+            self.breed();
+        } else {
+            self.gallery.clear();
+        }
+    }
+
+    // Replaces the gallery with fresh mutants of the current rule, all starting from the
+    // current board.
+    fn breed(&mut self) {
+        // Mutants are drawn from the rule table, which a Generations rule does not use.
+        if self.automata.generations().is_some() {
+            self.rule_error =
+                Some("Generations rules cannot be bred; load a rule table".to_string());
+            self.show_gallery = false;
+            self.gallery.clear();
+            return;
+        }
+        let rate = match self.input_mutation_rate.trim().parse::<f64>() {
+            Ok(rate) if (0.0..=1.0).contains(&rate) => rate,
+            _ => {
+                self.rule_error = Some("Mutation rate must be between 0 and 1".to_string());
+                return;
+            }
+        };
+        let mut rng = SmallRng::seed_from_u64(::rand::random());
+        self.gallery_mate = None;
+        self.gallery = (0..GALLERY_CHILDREN)
+            .map(|_| {
+                let mut rules = self.automata.rules().clone();
+                rules.mutate(rate, &mut rng);
+                let mut automata = self.automata.clone();
+                automata.set_rules(rules);
+                let image = Image::gen_image_color(
+                    self.grid_w as u16,
+                    (self.grid_h * self.history_length) as u16,
+                    BLACK,
+                );
+                let texture = Texture2D::from_image(&image);
+                texture.set_filter(FilterMode::Nearest);
+                let mut child = GalleryChild {
+                    history: VecDeque::from([automata.cells()]),
+                    automata,
+                    image,
+                    texture,
+                };
+                self.update_child_texture(&mut child);
+                child
+            })
+            .collect();
+    }
+
+    fn step_gallery(&mut self) {
+        let mut gallery = std::mem::take(&mut self.gallery);
+        for child in &mut gallery {
+            child.automata.step();
+            if child.history.len() == self.history_length {
+                child.history.pop_front();
+            }
+            child.history.push_back(child.automata.cells());
+            self.update_child_texture(child);
+        }
+        self.gallery = gallery;
+    }
+
+    // Children are drawn like the history view, oldest board at the top.
+    fn update_child_texture(&self, child: &mut GalleryChild) {
+        for (row_idx, cells) in child.history.iter().enumerate() {
+            for (idx, &state) in cells.iter().enumerate() {
+                let (x, y) = (idx % self.grid_w, idx / self.grid_w);
+                child.image.set_pixel(
+                    x as u32,
+                    (y + row_idx * self.grid_h) as u32,
+                    state_color(state, child.automata.generations()),
+                );
+            }
+        }
+        child.texture.update(&child.image);
+    }
+
+    // A child becomes the new parent; with a mate picked first (Shift + click) the new parent
+    // is a crossover of the two instead.
+    fn adopt_child(&mut self, index: usize) {
+        let child = self.gallery[index].automata.rules();
+        let rules = match self.gallery_mate.take() {
+            Some(mate) if mate != index => {
+                let mut rng = SmallRng::seed_from_u64(::rand::random());
+                match self.gallery[mate]
+                    .automata
+                    .rules()
+                    .crossover(child, &mut rng)
+                {
+                    Ok(rules) => rules,
+                    Err(err) => {
+                        self.rule_error = Some(err);
+                        return;
+                    }
+                }
+            }
+            _ => child.clone(),
+        };
+        self.load_rules(rules);
+        self.rulestring = None;
+        self.rule_error = None;
+        self.breed();
+    }
+
+    fn draw_gallery(&mut self) {
+        let rows = (self.grid_h * self.history_length) as f32;
+        let scale = (GALLERY_TILE / self.grid_w as f32).min(GALLERY_TILE / rows);
+        let tile = vec2(self.grid_w as f32 * scale, rows * scale);
+        let gap = 8.0;
+        let grid_w = GALLERY_COLUMNS as f32 * (tile.x + gap) + 16.0;
+        let grid_h = GALLERY_CHILDREN.div_ceil(GALLERY_COLUMNS) as f32 * (tile.y + gap);
+        let mut clicked = None;
+        let mut rebreed = false;
+        let mate = self.gallery_mate;
+        root_ui().window(
+            hash!("gallery"),
+            vec2((screen_width() - grid_w) * 0.5, 40.0),
+            vec2(grid_w, grid_h + 110.0),
+            |ui| {
+                for (i, child) in self.gallery.iter().enumerate() {
+                    let pos = vec2(
+                        (i % GALLERY_COLUMNS) as f32 * (tile.x + gap),
+                        (i / GALLERY_COLUMNS) as f32 * (tile.y + gap),
+                    );
+                    if widgets::Texture::new(child.texture.clone())
+                        .size(tile.x, tile.y)
+                        .position(pos)
+                        .ui(ui)
+                    {
+                        clicked = Some(i);
+                    }
+                }
+                let hint = match mate {
+                    Some(mate) => format!("Mate: child {}; click another to cross them", mate + 1),
+                    None => "Click a child to adopt it, Shift + click to pick a mate".to_string(),
+                };
+                ui.label(vec2(0.0, grid_h + 4.0), &hint);
+                ui.input_text(
+                    hash!("mutation_rate"),
+                    "Mutation rate",
+                    &mut self.input_mutation_rate,
+                );
+                if ui.button(None, "New children") {
+                    rebreed = true;
+                }
+            },
+        );
+        if let Some(index) = clicked {
+            if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+                self.gallery_mate = (self.gallery_mate != Some(index)).then_some(index);
+            } else {
+                self.adopt_child(index);
+            }
+        }
+        if rebreed {
+            self.breed();
+        }
+    }
+
+    fn draw_neighborhood_editor(&mut self, x: f32, y: f32) {
+        let cell = 24.0;
+        let side = (2 * EDITOR_RADIUS + 1) as f32 * cell;
+        root_ui().window(
+            hash!("nb_editor"),
+            vec2(x, y),
+            vec2(side + 24.0, side + 150.0),
+            |ui| {
+                for dy in -EDITOR_RADIUS..=EDITOR_RADIUS {
+                    for dx in -EDITOR_RADIUS..=EDITOR_RADIUS {
+                        let selected = self.editor_offsets.contains(&(dx, dy));
+                        let label = match (selected, dx == 0 && dy == 0) {
+                            (true, true) => "C",
+                            (false, true) => "c",
+                            (true, false) => "#",
+                            (false, false) => ".",
+                        };
+                        let pos = vec2(
+                            (dx + EDITOR_RADIUS) as f32 * cell,
+                            (dy + EDITOR_RADIUS) as f32 * cell,
+                        );
+                        if ui.button(pos, label) {
+                            if selected {
+                                self.editor_offsets.retain(|&offset| offset != (dx, dy));
+                            } else {
+                                self.editor_offsets.push((dx, dy));
+                            }
+                        }
+                    }
+                }
+                ui.label(
+                    vec2(0.0, side + 4.0),
+                    &format!(
+                        "Cells: {} / {}",
+                        self.editor_offsets.len(),
+                        max_cells(self.states)
+                    ),
+                );
+                if ui.button(None, "Load current") {
+                    self.editor_offsets = self.neighborhood.offsets();
+                }
+                if ui.button(None, "Clear") {
+                    self.editor_offsets.clear();
+                }
+                let count = self.editor_offsets.len();
+                if count == 0 || count > max_cells(self.states) {
+                    ui.label(
+                        None,
+                        &format!("Select 1-{} cells to apply", max_cells(self.states)),
+                    );
+                } else if ui.button(None, "Apply custom (rebuild)") {
+                    self.neighborhood_shape = NEIGHBORHOOD_SHAPES.len() - 1;
+                    self.apply_inputs();
+                }
+            },
+        );
+    }
+
+    // One icon per neighborhood pattern with its output to the right; clicking an icon moves
+    // its output to the next state.
+    fn draw_rule_editor(&mut self, y: f32) {
+        let patterns = self.automata.rules().patterns() as usize;
+        let per_page = RULE_EDITOR_COLUMNS * RULE_EDITOR_ROWS;
+        let pages = patterns.div_ceil(per_page);
+        self.rule_page = self.rule_page.min(pages - 1);
+        self.refresh_rule_icons();
+
+        let width = RULE_EDITOR_COLUMNS as f32 * (RULE_ICON_W + RULE_ICON_GAP) + 16.0;
+        let grid_h = RULE_EDITOR_ROWS as f32 * (RULE_ICON_H + RULE_ICON_GAP);
+        let first = self.rule_page * per_page;
+        let mut clicked = None;
+        let mut page = self.rule_page;
+        root_ui().window(
+            hash!("rule_editor"),
+            vec2(screen_width() - width - 12.0, y),
+            vec2(width, grid_h + 120.0),
+            |ui| {
+                for (i, (texture, size)) in self.rule_icons.iter().enumerate() {
+                    let pos = vec2(
+                        (i % RULE_EDITOR_COLUMNS) as f32 * (RULE_ICON_W + RULE_ICON_GAP),
+                        (i / RULE_EDITOR_COLUMNS) as f32 * (RULE_ICON_H + RULE_ICON_GAP),
+                    );
+                    if widgets::Texture::new(texture.clone())
+                        .size(size.x, size.y)
+                        .position(pos)
+                        .ui(ui)
+                    {
+                        clicked = Some(first + i);
+                    }
+                }
+                ui.label(
+                    vec2(0.0, grid_h + 4.0),
+                    &format!("Page {} / {} ({} patterns)", page + 1, pages, patterns),
+                );
+                if ui.button(None, "<<") {
+                    page = page.saturating_sub(10);
+                }
+                ui.same_line(0.0);
+                if ui.button(None, "<") {
+                    page = page.saturating_sub(1);
+                }
+                ui.same_line(0.0);
+                if ui.button(None, ">") {
+                    page += 1;
+                }
+                ui.same_line(0.0);
+                if ui.button(None, ">>") {
+                    page += 10;
+                }
+                ui.input_text(hash!("rule_page"), "Page", &mut self.input_rule_page);
+                if ui.button(None, "Go to page") {
+                    if let Ok(target) = self.input_rule_page.trim().parse::<usize>() {
+                        page = target.saturating_sub(1);
+                    }
+                }
+            },
+        );
+        if page != self.rule_page {
+            self.rule_page = page.min(pages - 1);
+            self.input_rule_page = (self.rule_page + 1).to_string();
+        }
+        if let Some(pattern) = clicked {
+            self.cycle_rule_output(pattern as u64);
+        }
+    }
+
+    fn cycle_rule_output(&mut self, pattern: u64) {
+        // The table has no effect while a Generations rule runs, so editing it leaves that rule.
+        if self.automata.generations().is_some() {
+            let rules = self.automata.rules().clone();
+            self.automata.set_rules(rules);
+        }
+        let rules = self.automata.rules_mut();
+        let states = rules.states() as u8;
+        let output = rules.get_new_cell(pattern);
+        rules.set_new_cell(pattern, (output + 1) % states);
+        self.rulestring = None;
+        self.refresh_rule_number();
+    }
+
+    fn refresh_rule_icons(&mut self) {
+        let rules = self.automata.rules();
+        let per_page = (RULE_EDITOR_COLUMNS * RULE_EDITOR_ROWS) as u64;
+        let first = self.rule_page as u64 * per_page;
+        let page_patterns = first..(first + per_page).min(rules.patterns());
+        let key = (
+            self.rule_page,
+            rules.neighborhood().offsets(),
+            rules.states(),
+            page_patterns
+                .clone()
+                .map(|pattern| rules.get_new_cell(pattern))
+                .collect::<Vec<u8>>(),
+        );
+        if key == self.rule_icons_key && !self.rule_icons.is_empty() {
+            return;
+        }
+        self.rule_icons = page_patterns
+            .map(|pattern| self.rule_icon(pattern))
+            .collect();
+        self.rule_icons_key = key;
+    }
+
+    // The pattern is drawn over the neighborhood's bounding box, and the output sits one
+    // column to its right on the centre cell's row. Also returns the size to draw it at.
+    fn rule_icon(&self, pattern: u64) -> (Texture2D, Vec2) {
+        let rules = self.automata.rules();
+        let offsets = rules.neighborhood().offsets();
+        let states = rules.states() as u64;
+        let min_x = offsets.iter().map(|o| o.0).min().unwrap_or(0);
+        let max_x = offsets.iter().map(|o| o.0).max().unwrap_or(0);
+        let min_y = offsets.iter().map(|o| o.1).min().unwrap_or(0);
+        let max_y = offsets.iter().map(|o| o.1).max().unwrap_or(0);
+        let box_w = (max_x - min_x + 1) as usize;
+        let box_h = (max_y - min_y + 1) as usize;
+
+        let (outside, gap) = RULE_ICON_COLORS;
+        let mut image = Image::gen_image_color(box_w as u16 + 2, box_h as u16, outside);
+        for y in 0..box_h as u32 {
+            image.set_pixel(box_w as u32, y, gap);
+            image.set_pixel(box_w as u32 + 1, y, gap);
+        }
+        let mut key = pattern;
+        for &(dx, dy) in &offsets {
+            let state = (key % states) as usize;
+            key /= states;
+            image.set_pixel((dx - min_x) as u32, (dy - min_y) as u32, PALETTE[state]);
+        }
+        let output = rules.get_new_cell(pattern) as usize;
+        let output_y = (-min_y).clamp(0, box_h as isize - 1) as u32;
+        image.set_pixel(box_w as u32 + 1, output_y, PALETTE[output]);
+
+        let cell = (RULE_ICON_W / (box_w + 2) as f32)
+            .min(RULE_ICON_H / box_h as f32)
+            .floor()
+            .max(1.0);
+        let texture = Texture2D::from_image(&image);
+        texture.set_filter(FilterMode::Nearest);
+        (
+            texture,
+            vec2((box_w + 2) as f32 * cell, box_h as f32 * cell),
+        )
     }
 
     fn sanitize_inputs(&mut self) {
         let only_digits = |s: &mut String| s.retain(|c| c.is_ascii_digit());
         only_digits(&mut self.input_grid_w);
         only_digits(&mut self.input_grid_h);
+        only_digits(&mut self.input_states);
         only_digits(&mut self.input_neighborhood_w);
         only_digits(&mut self.input_neighborhood_h);
+        only_digits(&mut self.input_neighborhood_radius);
         only_digits(&mut self.input_history_length);
+        only_digits(&mut self.input_rule_number);
+        only_digits(&mut self.input_rule_seed);
+        only_digits(&mut self.input_board_seed);
+        only_digits(&mut self.input_rule_page);
+        self.input_rulestring
+            .retain(|c| c.is_ascii_alphanumeric() || c == '/' || c == '-');
         self.input_spawn_chance
             .retain(|c| c.is_ascii_digit() || c == '.');
+        self.input_session_path.retain(|c| !c.is_control());
+        self.input_pattern_path.retain(|c| !c.is_control());
+        self.input_rule_path.retain(|c| !c.is_control());
+        self.input_png_path.retain(|c| !c.is_control());
+        only_digits(&mut self.input_png_scale);
+        self.input_record_path.retain(|c| !c.is_control());
+        only_digits(&mut self.input_record_delay);
+        only_digits(&mut self.input_record_scale);
+        only_digits(&mut self.input_record_max);
+        self.input_mutation_rate
+            .retain(|c| c.is_ascii_digit() || c == '.');
+        only_digits(&mut self.input_pattern_x);
+        only_digits(&mut self.input_pattern_y);
+    }
+
+    // Every text box, to spot typing; see `draw_ui`.
+    fn input_texts(&self) -> Vec<String> {
+        [
+            &self.input_grid_w,
+            &self.input_grid_h,
+            &self.input_neighborhood_w,
+            &self.input_neighborhood_h,
+            &self.input_neighborhood_radius,
+            &self.input_history_length,
+            &self.input_spawn_chance,
+            &self.input_states,
+            &self.input_rule_seed,
+            &self.input_board_seed,
+            &self.input_rule_number,
+            &self.input_rulestring,
+            &self.input_mutation_rate,
+            &self.input_rule_page,
+            &self.input_session_path,
+            &self.input_pattern_path,
+            &self.input_pattern_x,
+            &self.input_pattern_y,
+            &self.input_rule_path,
+            &self.input_png_path,
+            &self.input_png_scale,
+            &self.input_record_path,
+            &self.input_record_delay,
+            &self.input_record_scale,
+            &self.input_record_max,
+        ]
+        .into_iter()
+        .cloned()
+        .collect()
     }
 
     fn handle_input(&mut self) {
         let adjust = 0.005f32;
-        if is_key_pressed(KeyCode::Space) {
-            self.paused = !self.paused;
-        }
         if is_key_down(KeyCode::Up) {
             self.step_time = (self.step_time - adjust).max(0.001);
         }
         if is_key_down(KeyCode::Down) {
             self.step_time = (self.step_time + adjust).min(5.0);
         }
+        self.handle_camera();
+        self.handle_selection();
+        self.handle_painting();
+    }
+
+    // Runs after the UI has taken its input for the frame, so keys typed into a text box can be
+    // told apart from shortcuts.
+    fn handle_keys(&mut self) {
+        if is_key_pressed(KeyCode::Space) {
+            self.paused = !self.paused;
+        }
         if is_key_pressed(KeyCode::R) {
             self.reset();
         }
@@ -270,10 +1654,193 @@ impl Game {
         if is_key_pressed(KeyCode::H) {
             self.show_history = !self.show_history;
         }
+        if is_key_pressed(KeyCode::N) {
+            if !self.show_neighborhood_editor {
+                self.editor_offsets = self.neighborhood.offsets();
+            }
+            self.show_neighborhood_editor = !self.show_neighborhood_editor;
+        }
+        if is_key_pressed(KeyCode::C) {
+            self.automata.fill_region(0, 0, self.grid_w, self.grid_h, 0);
+            self.refresh_current_history();
+        }
+        let max_paint_state = (self.automata.states() - 1) as u8;
+        if is_key_pressed(KeyCode::LeftBracket) {
+            self.paint_state = self.paint_state.saturating_sub(1).max(1);
+        }
+        if is_key_pressed(KeyCode::RightBracket) {
+            self.paint_state += 1;
+        }
+        self.paint_state = self.paint_state.min(max_paint_state);
+        if is_key_pressed(KeyCode::F5) {
+            self.save_session();
+        }
+        if is_key_pressed(KeyCode::F9) {
+            self.load_session();
+        }
+        if is_key_pressed(KeyCode::P) {
+            self.save_screenshot();
+        }
+        if is_key_pressed(KeyCode::G) {
+            self.toggle_recording();
+        }
+        if is_key_pressed(KeyCode::B) {
+            self.toggle_gallery();
+        }
+        if is_key_pressed(KeyCode::A) {
+            self.classify_rule();
+        }
+        if is_key_pressed(KeyCode::T) {
+            self.show_rule_editor = !self.show_rule_editor;
+        }
+        if is_key_pressed(KeyCode::F) {
+            self.fit_view();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            self.selection = None;
+        }
+    }
+
+    fn handle_selection(&mut self) {
+        let selecting = is_mouse_button_down(MouseButton::Left)
+            && (is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl));
+        if !selecting {
+            self.selection_start = None;
+            return;
+        }
+        let position = Vec2::from(mouse_position());
+        if self.selection_start.is_none() && root_ui().is_mouse_over(position) {
+            return;
+        }
+        if let Some((x, y)) = self.screen_to_cell(position) {
+            let (start_x, start_y) = *self.selection_start.get_or_insert((x, y));
+            self.selection = Some((
+                start_x.min(x),
+                start_y.min(y),
+                start_x.abs_diff(x) + 1,
+                start_y.abs_diff(y) + 1,
+            ));
+        }
+    }
+
+    fn fit_view(&mut self) {
+        self.zoom = 1.0;
+        self.pan = Vec2::ZERO;
+    }
+
+    // The wheel zooms about the cursor; middle drag or shift + left drag pans.
+    fn handle_camera(&mut self) {
+        let position = Vec2::from(mouse_position());
+        let over_ui = root_ui().is_mouse_over(position);
+
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0.0 && !over_ui {
+            self.zoom_at(position, ZOOM_STEP.powf(wheel.signum()));
+        }
+
+        let panning = is_mouse_button_down(MouseButton::Middle)
+            || (is_mouse_button_down(MouseButton::Left)
+                && (is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift)));
+        if let (true, Some(last)) = (panning, self.last_pan_position) {
+            self.pan += position - last;
+        }
+        self.last_pan_position =
+            (panning && (self.last_pan_position.is_some() || !over_ui)).then_some(position);
+    }
+
+    // The board scales about its centre, so moving the centre by the cursor's offset times
+    // (1 - factor) keeps the cell under the cursor in place.
+    fn zoom_at(&mut self, position: Vec2, factor: f32) {
+        let (board, _) = self.board_layout();
+        let new_zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let factor = new_zoom / self.zoom;
+        self.pan += (position - board.center()) * (1.0 - factor);
+        self.zoom = new_zoom;
+    }
+
+    // Left drag paints `paint_state` and right drag erases, on the current board only.
+    fn handle_painting(&mut self) {
+        let state = if self.last_pan_position.is_some() || self.selection_start.is_some() {
+            self.last_paint = None;
+            return;
+        } else if is_mouse_button_down(MouseButton::Left) {
+            self.paint_state
+        } else if is_mouse_button_down(MouseButton::Right) {
+            0
+        } else {
+            self.last_paint = None;
+            return;
+        };
+        let position = Vec2::from(mouse_position());
+        let cell = match self.screen_to_cell(position) {
+            Some(cell) if !root_ui().is_mouse_over(position) => cell,
+            _ => {
+                self.last_paint = None;
+                return;
+            }
+        };
+
+        // Fast drags skip cells between frames, so paint the line from the last painted cell.
+        let from = self.last_paint.unwrap_or(cell);
+        let steps = from.0.abs_diff(cell.0).max(from.1.abs_diff(cell.1)).max(1);
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let x = from.0 as f32 + (cell.0 as f32 - from.0 as f32) * t;
+            let y = from.1 as f32 + (cell.1 as f32 - from.1 as f32) * t;
+            self.automata
+                .set_cell(x.round() as usize, y.round() as usize, state);
+        }
+        self.last_paint = Some(cell);
+        self.refresh_current_history();
+    }
+
+    fn refresh_current_history(&mut self) {
+        if let Some(current) = self.cells_history.back_mut() {
+            *current = self.automata.cells();
+        }
+    }
+
+    // Where the texture is drawn on screen and how many pixels one cell covers, after zoom and
+    // pan are applied to the view that fits the window.
+    fn board_layout(&self) -> (Rect, f32) {
+        let win_w = screen_width();
+        let win_h = screen_height();
+        let rows_to_show = if self.show_history {
+            (self.grid_h * self.history_length) as f32
+        } else {
+            self.grid_h as f32
+        };
+        let scale = (win_w / self.grid_w as f32).min(win_h / rows_to_show) * self.zoom;
+        let draw_w = self.grid_w as f32 * scale;
+        let draw_h = rows_to_show * scale;
+        let pos_x = ((win_w - draw_w) * 0.5 + self.pan.x).floor();
+        let pos_y = ((win_h - draw_h) * 0.5 + self.pan.y).floor();
+        (Rect::new(pos_x, pos_y, draw_w, draw_h), scale)
+    }
+
+    // The current board is the bottom block of rows in the history view, so positions over
+    // older rows map to no cell.
+    fn screen_to_cell(&self, position: Vec2) -> Option<(usize, usize)> {
+        let (rect, scale) = self.board_layout();
+        if position.x < rect.x || position.y < rect.y {
+            return None;
+        }
+        let x = ((position.x - rect.x) / scale) as usize;
+        let mut y = ((position.y - rect.y) / scale) as usize;
+        if self.show_history {
+            y = y.checked_sub(self.current_board_row())?;
+        }
+        (x < self.grid_w && y < self.grid_h).then_some((x, y))
+    }
+
+    // First texture row of the current board in the history view.
+    fn current_board_row(&self) -> usize {
+        self.cells_history.len().saturating_sub(1) * self.grid_h
     }
 
     pub fn step(&mut self) {
         self.handle_input();
+        self.poll_analysis();
         self.time_since_last_step += get_frame_time();
         if self.time_since_last_step < self.step_time || self.paused {
             return;
@@ -281,6 +1848,8 @@ impl Game {
         self.automata.step();
         self.time_since_last_step = 0.0;
         self.add_history();
+        self.record_frame();
+        self.step_gallery();
     }
 
     pub fn draw(&mut self) {
@@ -289,18 +1858,7 @@ impl Game {
 
         clear_background(Color::from_rgba(12, 18, 28, 255));
 
-        let win_w = screen_width();
-        let win_h = screen_height();
-        let rows_to_show = if self.show_history {
-            (self.grid_h * self.history_length) as f32
-        } else {
-            self.grid_h as f32
-        };
-        let scale = (win_w / self.grid_w as f32).min(win_h / rows_to_show);
-        let draw_w = self.grid_w as f32 * scale;
-        let draw_h = rows_to_show * scale;
-        let pos_x = ((win_w - draw_w) * 0.5).floor();
-        let pos_y = ((win_h - draw_h) * 0.5).floor();
+        let (board, _) = self.board_layout();
 
         let source_rect = if self.show_history {
             None
@@ -320,22 +1878,61 @@ impl Game {
 
         draw_texture_ex(
             &self.texture,
-            pos_x,
-            pos_y,
+            board.x,
+            board.y,
             WHITE,
             DrawTextureParams {
-                dest_size: Some(vec2(draw_w, draw_h)),
+                dest_size: Some(vec2(board.w, board.h)),
                 source: source_rect,
                 ..Default::default()
             },
         );
 
+        if let Some((x, y, width, height)) = self.selection {
+            let (board, scale) = self.board_layout();
+            let top = if self.show_history {
+                self.current_board_row()
+            } else {
+                0
+            };
+            draw_rectangle_lines(
+                board.x + x as f32 * scale,
+                board.y + (top + y) as f32 * scale,
+                width as f32 * scale,
+                height as f32 * scale,
+                2.0,
+                YELLOW,
+            );
+        }
+
         let info = format!(
-            "Step: {:.3}s (Up/Down to adjust) | {} | View: {}",
+            "Step: {:.3}s (Up/Down to adjust) | {} | View: {} | Paint: {} | Seeds: rule {}, board {} | Lambda: {:.3}",
             self.step_time,
             if self.paused { "Paused" } else { "Running" },
-            if self.show_history { "History" } else { "Current" }
+            if self.show_history {
+                "History"
+            } else {
+                "Current"
+            },
+            self.paint_state,
+            self.rule_seed,
+            self.board_seed,
+            self.automata.rules().lambda()
         );
+        let info = match self.current_analysis() {
+            _ if self.classifying.is_some() => format!("{} | Class: classifying...", info),
+            Some(report) => format!(
+                "{} | Class: {} ({})",
+                info,
+                report.behavior.class(),
+                report.behavior.label()
+            ),
+            None => format!("{} | Class: - (A to classify)", info),
+        };
+        let info = match &self.recording {
+            Some(recording) => format!("{} | Recording: {} frames", info, recording.frames()),
+            None => info,
+        };
         draw_text(&info, 12.0, 24.0, 20.0, LIGHTGRAY);
     }
 }
@@ -0,0 +1,16 @@
+#![deny(non_snake_case)]
+
+// The simulation and file formats, with no window or macroquad dependency. The sandbox
+// binary adds the UI in `game.rs`; `src/bin/batch.rs` runs rules headlessly.
+pub mod analysis;
+pub mod automata;
+pub mod config;
+pub mod neighborhood;
+pub mod pattern;
+pub mod recording;
+pub mod rulefile;
+pub mod rules;
+pub mod rulestring;
+pub mod session;
+pub mod settings;
+pub mod snapshot;
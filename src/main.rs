@@ -1,26 +1,160 @@
 #![deny(non_snake_case)]
 
-mod automata;
-mod config;
 mod game;
-mod rules;
 
-use config::{GRID_H, GRID_W, HISTORY_LENGTH, NEIGHBORHOOD_H, NEIGHBORHOOD_W, SPAWN_CHANCE};
-use game::Game;
+use cellular_automata::automata::Automata;
+use cellular_automata::neighborhood::Neighborhood;
+use cellular_automata::settings::Settings;
+use cellular_automata::snapshot;
+use clap::Parser;
+use game::{color_bytes, state_color, Game};
 use macroquad::prelude::*;
 
-#[macroquad::main("Hello World")]
-async fn main() {
+// Flags override the config file, which overrides the defaults in `src/config.rs`.
+#[derive(Parser)]
+#[command(about = "Interactive cellular automata sandbox")]
+struct Cli {
+    #[arg(long, help = "TOML file with any of the settings below")]
+    config: Option<String>,
+    #[arg(long, help = "Board width in cells")]
+    grid_w: Option<usize>,
+    #[arg(long, help = "Board height in cells")]
+    grid_h: Option<usize>,
+    #[arg(long, help = "Number of boards kept in the history view")]
+    history_length: Option<usize>,
+    #[arg(long, help = "Neighborhood rectangle width")]
+    neighborhood_w: Option<usize>,
+    #[arg(long, help = "Neighborhood rectangle height")]
+    neighborhood_h: Option<usize>,
+    #[arg(long, help = "Number of cell states")]
+    states: Option<usize>,
+    #[arg(long, help = "Chance (0-1) that a cell starts alive")]
+    spawn_chance: Option<f32>,
+    #[arg(long, help = "Seed for the random rule table")]
+    rule_seed: Option<u64>,
+    #[arg(long, help = "Seed for the initial board")]
+    board_seed: Option<u64>,
+    #[arg(long, help = "Seconds between steps")]
+    step_time: Option<f32>,
+    #[arg(long, help = "Window width in pixels")]
+    window_w: Option<u32>,
+    #[arg(long, help = "Window height in pixels")]
+    window_h: Option<u32>,
+    #[arg(
+        long,
+        help = "Render the spacetime diagram to this PNG file without opening a window"
+    )]
+    png: Option<String>,
+    #[arg(long, default_value_t = 256, help = "Generations rendered by --png")]
+    generations: usize,
+    #[arg(long, default_value_t = 1, help = "Pixels per cell for --png")]
+    scale: usize,
+}
+
+impl Cli {
+    fn settings(&self) -> Result<Settings, String> {
+        let mut settings = match &self.config {
+            Some(path) => Settings::load(path)?,
+            None => Settings::default(),
+        };
+        settings.grid_w = self.grid_w.unwrap_or(settings.grid_w);
+        settings.grid_h = self.grid_h.unwrap_or(settings.grid_h);
+        settings.history_length = self.history_length.unwrap_or(settings.history_length);
+        settings.neighborhood_w = self.neighborhood_w.unwrap_or(settings.neighborhood_w);
+        settings.neighborhood_h = self.neighborhood_h.unwrap_or(settings.neighborhood_h);
+        settings.states = self.states.unwrap_or(settings.states);
+        settings.spawn_chance = self.spawn_chance.unwrap_or(settings.spawn_chance);
+        settings.rule_seed = self.rule_seed.or(settings.rule_seed);
+        settings.board_seed = self.board_seed.or(settings.board_seed);
+        settings.step_time = self.step_time.unwrap_or(settings.step_time);
+        settings.window_w = self.window_w.unwrap_or(settings.window_w);
+        settings.window_h = self.window_h.unwrap_or(settings.window_h);
+        settings.validate()?;
+        Ok(settings)
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let settings = cli.settings().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    });
+    if let Some(path) = &cli.png {
+        if let Err(err) = render_png(&settings, path, cli.generations, cli.scale) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let conf = Conf {
+        window_title: "Hello World".to_string(),
+        window_width: settings.window_w as i32,
+        window_height: settings.window_h as i32,
+        ..Default::default()
+    };
+    macroquad::Window::from_config(conf, run(settings));
+}
+
+// Renders the spacetime diagram of the configured automata without opening a window.
+fn render_png(
+    settings: &Settings,
+    path: &str,
+    generations: usize,
+    scale: usize,
+) -> Result<(), String> {
+    if generations == 0 {
+        return Err("generations must be at least 1".to_string());
+    }
+    let mut automata = Automata::with_seeds(
+        settings.grid_w,
+        settings.grid_h,
+        Neighborhood::Rectangle {
+            width: settings.neighborhood_w,
+            height: settings.neighborhood_h,
+        },
+        settings.states,
+        settings.spawn_chance,
+        settings.rule_seed.unwrap_or_else(::rand::random),
+        settings.board_seed.unwrap_or_else(::rand::random),
+    );
+    let height = settings.grid_h * generations;
+    snapshot::check_scale(settings.grid_w, height, scale)?;
+    let rgba = snapshot::spacetime(&mut automata, generations, |state| {
+        color_bytes(state_color(state, None))
+    });
+    snapshot::write_png(path, settings.grid_w, height, &rgba, scale)?;
+    println!(
+        "Wrote {} generations to {} (rule seed {}, board seed {})",
+        generations,
+        path,
+        automata.rule_seed(),
+        automata.board_seed()
+    );
+    Ok(())
+}
+
+async fn run(settings: Settings) {
     let mut game = Game::new(
-        GRID_W,
-        GRID_H,
-        HISTORY_LENGTH,
-        NEIGHBORHOOD_W,
-        NEIGHBORHOOD_H,
-        SPAWN_CHANCE,
+        settings.grid_w,
+        settings.grid_h,
+        settings.history_length,
+        settings.neighborhood_w,
+        settings.neighborhood_h,
+        settings.states,
+        settings.spawn_chance,
+        settings.rule_seed,
+        settings.board_seed,
+        settings.step_time,
     );
 
+    // Closing the window is held back for one frame so a recording can be finished.
+    prevent_quit();
     loop {
+        if is_quit_requested() {
+            game.finish_recording();
+            break;
+        }
         game.step();
         game.draw();
 
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+// Which cells around the centre feed the rule table. Offsets are `(dx, dy)` with y growing
+// downwards, and are always listed column by column (dx outer, dy inner) because that order
+// decides which bit of the neighborhood hash each cell lands in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Neighborhood {
+    Rectangle { width: usize, height: usize },
+    Moore(usize),
+    VonNeumann(usize),
+    Cross(usize),
+    // Hexagonal grid emulated on squares: the 3x3 block minus the north-east and south-west
+    // corners, as in Golly.
+    Hexagonal,
+    // Cells whose Euclidean distance from the centre is at most `radius`.
+    Circular(usize),
+    Custom(Vec<(isize, isize)>),
+}
+
+impl Neighborhood {
+    pub fn custom(offsets: impl IntoIterator<Item = (isize, isize)>) -> Result<Self, String> {
+        let mut offsets: Vec<(isize, isize)> = offsets.into_iter().collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        if offsets.is_empty() {
+            return Err("neighborhood needs at least one cell".to_string());
+        }
+        let min_y = offsets.iter().map(|&(_, dy)| dy).min().unwrap_or(0);
+        let max_y = offsets.iter().map(|&(_, dy)| dy).max().unwrap_or(0);
+        if max_y - min_y >= u64::BITS as isize {
+            return Err("neighborhood can span at most 64 rows".to_string());
+        }
+        Ok(Neighborhood::Custom(offsets))
+    }
+
+    pub fn offsets(&self) -> Vec<(isize, isize)> {
+        match self {
+            Neighborhood::Rectangle { width, height } => {
+                let start_x = -((*width as isize - 1) / 2);
+                let end_x = *width as isize / 2;
+                let start_y = -((*height as isize - 1) / 2);
+                let end_y = *height as isize / 2;
+                let mut offsets = Vec::new();
+                for dx in start_x..=end_x {
+                    for dy in start_y..=end_y {
+                        offsets.push((dx, dy));
+                    }
+                }
+                offsets
+            }
+            Neighborhood::Moore(radius) => square(*radius, |_, _| true),
+            Neighborhood::VonNeumann(radius) => {
+                square(*radius, |dx, dy| dx.abs() + dy.abs() <= *radius as isize)
+            }
+            Neighborhood::Cross(radius) => square(*radius, |dx, dy| dx == 0 || dy == 0),
+            Neighborhood::Hexagonal => square(1, |dx, dy| dx != -dy || dx == 0),
+            Neighborhood::Circular(radius) => {
+                let radius = *radius as isize;
+                square(radius as usize, |dx, dy| {
+                    dx * dx + dy * dy <= radius * radius
+                })
+            }
+            Neighborhood::Custom(offsets) => offsets.clone(),
+        }
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.offsets().len()
+    }
+
+    pub fn radius(&self) -> Option<usize> {
+        match self {
+            Neighborhood::Moore(radius)
+            | Neighborhood::VonNeumann(radius)
+            | Neighborhood::Cross(radius)
+            | Neighborhood::Circular(radius) => Some(*radius),
+            _ => None,
+        }
+    }
+
+    pub fn with_radius(&self, radius: usize) -> Self {
+        match self {
+            Neighborhood::Moore(_) => Neighborhood::Moore(radius),
+            Neighborhood::VonNeumann(_) => Neighborhood::VonNeumann(radius),
+            Neighborhood::Cross(_) => Neighborhood::Cross(radius),
+            Neighborhood::Circular(_) => Neighborhood::Circular(radius),
+            other => other.clone(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Neighborhood::Rectangle { width, height } => format!("{}x{} rectangle", width, height),
+            Neighborhood::Moore(radius) => format!("Moore r={}", radius),
+            Neighborhood::VonNeumann(radius) => format!("von Neumann r={}", radius),
+            Neighborhood::Cross(radius) => format!("Cross r={}", radius),
+            Neighborhood::Hexagonal => "Hexagonal".to_string(),
+            Neighborhood::Circular(radius) => format!("Circular r={}", radius),
+            Neighborhood::Custom(offsets) => format!("Custom ({} cells)", offsets.len()),
+        }
+    }
+}
+
+fn square(radius: usize, keep: impl Fn(isize, isize) -> bool) -> Vec<(isize, isize)> {
+    let radius = radius as isize;
+    let mut offsets = Vec::new();
+    for dx in -radius..=radius {
+        for dy in -radius..=radius {
+            if keep(dx, dy) {
+                offsets.push((dx, dy));
+            }
+        }
+    }
+    offsets
+}
@@ -0,0 +1,919 @@
+use std::collections::HashMap;
+use std::fs;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::CharIndices;
+
+use crate::automata::Automata;
+
+// Golly wraps RLE lines at 70 characters.
+const RLE_LINE_LENGTH: usize = 70;
+// Multi-state RLE writes states 1-24 as `A`-`X` and higher ones behind a prefix `p`-`y`,
+// each prefix adding another 24.
+const RLE_LETTERS: u8 = 24;
+const RLE_MAX_STATE: usize = 255;
+// Macrocell patterns can describe astronomically large boards; only the bounding box of their
+// live cells is expanded, and only up to this many cells. RLE headers are held to it too.
+const MAX_PATTERN_CELLS: u64 = 1 << 26;
+const MACROCELL_MAX_LEVEL: u32 = 62;
+
+// A rectangle of cell states in row-major order, as read from or written to a pattern file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<u8>,
+    pub rule: Option<String>,
+}
+
+impl Pattern {
+    // The region is clipped to the board.
+    pub fn from_board(
+        automata: &Automata,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        let (grid_w, grid_h) = automata.size();
+        let width = width.min(grid_w.saturating_sub(x));
+        let height = height.min(grid_h.saturating_sub(y));
+        let cells = (y..y + height)
+            .flat_map(|cell_y| {
+                (x..x + width).map(move |cell_x| automata.get_cell(cell_x, cell_y).unwrap_or(0))
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            cells,
+            rule: None,
+        }
+    }
+
+    // Dead cells of the pattern overwrite the board too; cells that fall off the board are
+    // dropped, however far off the offset puts them.
+    pub fn place(&self, automata: &mut Automata, x: usize, y: usize) {
+        for (idx, &state) in self.cells.iter().enumerate() {
+            let cell_x = x.saturating_add(idx % self.width);
+            let cell_y = y.saturating_add(idx / self.width);
+            automata.set_cell(cell_x, cell_y, state);
+        }
+    }
+}
+
+// The format follows the file extension: `.cells`, `.mc`, and RLE for anything else.
+pub fn load(path: &str) -> Result<Pattern, String> {
+    let text =
+        fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+    let pattern = match extension(path).as_str() {
+        "cells" => parse_cells(&text),
+        "mc" => parse_macrocell(&text),
+        _ => parse_rle(&text),
+    };
+    pattern.map_err(|err| format!("{}: {}", path, err))
+}
+
+pub fn save(path: &str, pattern: &Pattern) -> Result<(), String> {
+    let text = match extension(path).as_str() {
+        "cells" => write_cells(pattern)?,
+        "mc" => write_macrocell(pattern),
+        _ => write_rle(pattern),
+    };
+    fs::write(path, text).map_err(|err| format!("could not write {}: {}", path, err))
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn error_at(line: usize, column: usize, message: &str) -> String {
+    format!("line {}, column {}: {}", line, column, message)
+}
+
+// Golly's extended RLE: `#` comment lines, a `x = 3, y = 2, rule = B3/S23` header and runs
+// such as `3o$b2o!`. Two-state patterns use `b` and `o` (any other lowercase letter is also
+// alive); multi-state ones use `.` and `A`, `B`, ..., `pA`, ... .
+pub fn parse_rle(text: &str) -> Result<Pattern, String> {
+    let mut pattern: Option<Pattern> = None;
+    let (mut x, mut y) = (0usize, 0usize);
+    let mut run: Option<usize> = None;
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_number = line_idx + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let pattern = match &mut pattern {
+            Some(pattern) => pattern,
+            None => {
+                pattern = Some(parse_rle_header(trimmed, line_number)?);
+                continue;
+            }
+        };
+
+        let mut chars = line.char_indices().peekable();
+        while let Some((idx, c)) = chars.next() {
+            let column = idx + 1;
+            let state = match c {
+                ' ' | '\t' | '\r' => continue,
+                '0'..='9' => {
+                    let digit = c as usize - '0' as usize;
+                    run = run
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|count| count.checked_add(digit));
+                    if run.is_none() {
+                        return Err(error_at(line_number, column, "run count is too large"));
+                    }
+                    continue;
+                }
+                '$' => {
+                    y = y
+                        .checked_add(run.take().unwrap_or(1))
+                        .ok_or_else(|| error_at(line_number, column, "run count is too large"))?;
+                    x = 0;
+                    continue;
+                }
+                '!' => return Ok(pattern.clone()),
+                _ => parse_rle_state(c, &mut chars)
+                    .ok_or_else(|| error_at(line_number, column, &format!("unexpected '{}'", c)))?,
+            };
+
+            let count = run.take().unwrap_or(1);
+            let end = x.checked_add(count).filter(|&end| end <= pattern.width);
+            if end.is_none() || (y >= pattern.height && state != 0) {
+                return Err(error_at(
+                    line_number,
+                    column,
+                    &format!(
+                        "cells fall outside the {}x{} header size",
+                        pattern.width, pattern.height
+                    ),
+                ));
+            }
+            if state != 0 {
+                let start = x + y * pattern.width;
+                pattern.cells[start..start + count].fill(state);
+            }
+            x += count;
+        }
+    }
+
+    match pattern {
+        Some(pattern) => Ok(pattern),
+        None => Err("missing 'x = ..., y = ...' header line".to_string()),
+    }
+}
+
+fn parse_rle_header(line: &str, line_number: usize) -> Result<Pattern, String> {
+    let mut width = None;
+    let mut height = None;
+    let mut rule = None;
+    for field in line.split(',') {
+        let (key, value) = field.split_once('=').ok_or_else(|| {
+            error_at(
+                line_number,
+                1,
+                &format!("expected 'key = value', found '{}'", field.trim()),
+            )
+        })?;
+        let value = value.trim();
+        let parse_size = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| error_at(line_number, 1, &format!("'{}' is not a size", value)))
+        };
+        match key.trim() {
+            "x" => width = Some(parse_size()?),
+            "y" => height = Some(parse_size()?),
+            "rule" => rule = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    let (Some(width), Some(height)) = (width, height) else {
+        return Err(error_at(line_number, 1, "header needs both x and y"));
+    };
+    let cells = width
+        .checked_mul(height)
+        .filter(|&cells| cells as u64 <= MAX_PATTERN_CELLS)
+        .ok_or_else(|| {
+            error_at(
+                line_number,
+                1,
+                &format!(
+                    "{}x{} is more than {} cells",
+                    width, height, MAX_PATTERN_CELLS
+                ),
+            )
+        })?;
+    Ok(Pattern {
+        width,
+        height,
+        cells: vec![0; cells],
+        rule,
+    })
+}
+
+fn parse_rle_state(c: char, chars: &mut Peekable<CharIndices>) -> Option<u8> {
+    let letter_state = |prefix: usize, letter: char| {
+        let state = prefix * RLE_LETTERS as usize + (letter as usize - 'A' as usize) + 1;
+        (state <= RLE_MAX_STATE).then_some(state as u8)
+    };
+    match c {
+        '.' | 'b' => Some(0),
+        'A'..='X' => letter_state(0, c),
+        'p'..='y'
+            if chars
+                .peek()
+                .is_some_and(|(_, next)| next.is_ascii_uppercase()) =>
+        {
+            let (_, letter) = chars.next()?;
+            if !('A'..='X').contains(&letter) {
+                return None;
+            }
+            letter_state(c as usize - 'p' as usize + 1, letter)
+        }
+        'a'..='z' => Some(1),
+        _ => None,
+    }
+}
+
+pub fn write_rle(pattern: &Pattern) -> String {
+    let multi_state = pattern.cells.iter().any(|&state| state > 1);
+    let mut out = format!("x = {}, y = {}", pattern.width, pattern.height);
+    if let Some(rule) = &pattern.rule {
+        out.push_str(&format!(", rule = {}", rule));
+    }
+    out.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut row_breaks = 0;
+    for (row_idx, row) in pattern.cells.chunks(pattern.width.max(1)).enumerate() {
+        if row_idx > 0 {
+            row_breaks += 1;
+        }
+        // Trailing dead cells of a row are implied.
+        let end = row
+            .iter()
+            .rposition(|&state| state != 0)
+            .map_or(0, |idx| idx + 1);
+        if end == 0 {
+            continue;
+        }
+        if row_breaks > 0 {
+            tokens.push(rle_token(row_breaks, "$"));
+            row_breaks = 0;
+        }
+        let mut x = 0;
+        while x < end {
+            let state = row[x];
+            let count = row[x..end].iter().take_while(|&&s| s == state).count();
+            tokens.push(rle_token(count, &rle_symbol(state, multi_state)));
+            x += count;
+        }
+    }
+    tokens.push("!".to_string());
+
+    let mut line = String::new();
+    for token in tokens {
+        if line.len() + token.len() > RLE_LINE_LENGTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        line.push_str(&token);
+    }
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
+
+fn rle_token(count: usize, symbol: &str) -> String {
+    if count == 1 {
+        symbol.to_string()
+    } else {
+        format!("{}{}", count, symbol)
+    }
+}
+
+fn rle_symbol(state: u8, multi_state: bool) -> String {
+    match (state, multi_state) {
+        (0, false) => "b".to_string(),
+        (_, false) => "o".to_string(),
+        (0, true) => ".".to_string(),
+        (_, true) => {
+            let prefix = (state - 1) / RLE_LETTERS;
+            let letter = (b'A' + (state - 1) % RLE_LETTERS) as char;
+            if prefix == 0 {
+                letter.to_string()
+            } else {
+                format!("{}{}", (b'p' + prefix - 1) as char, letter)
+            }
+        }
+    }
+}
+
+// LifeWiki plaintext: `!` comment lines, then one line per row with `.` for dead and `O` for
+// live cells. Short rows are padded with dead cells.
+pub fn parse_cells(text: &str) -> Result<Pattern, String> {
+    let mut rows: Vec<Vec<u8>> = Vec::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        if line.starts_with('!') {
+            continue;
+        }
+        let row = line
+            .trim_end()
+            .char_indices()
+            .map(|(idx, c)| match c {
+                '.' => Ok(0),
+                'O' | '*' => Ok(1),
+                _ => Err(error_at(
+                    line_idx + 1,
+                    idx + 1,
+                    &format!("expected '.' or 'O', found '{}'", c),
+                )),
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        rows.push(row);
+    }
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut cells = vec![0; width * rows.len()];
+    for (row, cells_row) in rows.iter().zip(cells.chunks_mut(width.max(1))) {
+        cells_row[..row.len()].copy_from_slice(row);
+    }
+    Ok(Pattern {
+        width,
+        height: rows.len(),
+        cells,
+        rule: None,
+    })
+}
+
+pub fn write_cells(pattern: &Pattern) -> Result<String, String> {
+    if pattern.cells.iter().any(|&state| state > 1) {
+        return Err("plaintext .cells patterns can only hold two states".to_string());
+    }
+    let mut out = String::new();
+    if let Some(rule) = &pattern.rule {
+        out.push_str(&format!("!Rule: {}\n", rule));
+    }
+    for row in pattern.cells.chunks(pattern.width.max(1)) {
+        out.extend(row.iter().map(|&state| if state == 0 { '.' } else { 'O' }));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+// Macrocell nodes are numbered from 1 in file order and 0 is the empty node. Two-state files
+// have 8x8 leaves written as rows of `.` and `*` ended by `$`; multi-state files have 2x2
+// leaves written as `1 nw ne sw se`. Larger nodes are `level nw ne sw se` for a square of
+// side 2^level.
+enum MacrocellNode {
+    Leaf { level: u32, cells: Vec<u8> },
+    Inner { level: u32, children: [usize; 4] },
+}
+
+impl MacrocellNode {
+    fn level(&self) -> u32 {
+        match self {
+            MacrocellNode::Leaf { level, .. } | MacrocellNode::Inner { level, .. } => *level,
+        }
+    }
+}
+
+// Live cells of a node as (min_x, min_y, max_x, max_y), relative to its top-left corner.
+type Bounds = Option<(u64, u64, u64, u64)>;
+
+pub fn parse_macrocell(text: &str) -> Result<Pattern, String> {
+    let mut nodes: Vec<MacrocellNode> = Vec::new();
+    let mut bounds: Vec<Bounds> = vec![None];
+    let mut rule = None;
+    let mut header_seen = false;
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_number = line_idx + 1;
+        let trimmed = line.trim();
+        if !header_seen {
+            if !trimmed.starts_with("[M2]") {
+                return Err(error_at(line_number, 1, "expected a '[M2]' header"));
+            }
+            header_seen = true;
+            continue;
+        }
+        if let Some(comment) = trimmed.strip_prefix('#') {
+            if let Some(rulestring) = comment.strip_prefix('R') {
+                rule = Some(rulestring.trim().to_string());
+            }
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let node = if trimmed.starts_with(['.', '*', '$']) {
+            parse_macrocell_leaf(line, line_number)?
+        } else {
+            parse_macrocell_inner(line, line_number, &nodes)?
+        };
+        bounds.push(macrocell_bounds(&node, &bounds));
+        nodes.push(node);
+    }
+
+    if !header_seen {
+        return Err(error_at(1, 1, "expected a '[M2]' header"));
+    }
+    // The last node is the root. A file with no nodes at all, as written for an empty board,
+    // is an empty pattern like any root without live cells.
+    let (min_x, min_y, max_x, max_y) = match bounds[nodes.len()] {
+        Some(bounds) => bounds,
+        None => {
+            return Ok(Pattern {
+                width: 0,
+                height: 0,
+                cells: Vec::new(),
+                rule,
+            })
+        }
+    };
+    let width = max_x - min_x + 1;
+    let height = max_y - min_y + 1;
+    if width.saturating_mul(height) > MAX_PATTERN_CELLS {
+        return Err(format!(
+            "live cells span {}x{}, more than {} cells",
+            width, height, MAX_PATTERN_CELLS
+        ));
+    }
+
+    let mut pattern = Pattern {
+        width: width as usize,
+        height: height as usize,
+        cells: vec![0; (width * height) as usize],
+        rule,
+    };
+    paint_macrocell(
+        &nodes,
+        &bounds,
+        nodes.len(),
+        -(min_x as i64),
+        -(min_y as i64),
+        &mut pattern,
+    );
+    Ok(pattern)
+}
+
+fn parse_macrocell_leaf(line: &str, line_number: usize) -> Result<MacrocellNode, String> {
+    let mut cells = vec![0u8; 64];
+    let (mut x, mut y) = (0, 0);
+    for (idx, c) in line.trim_end().char_indices() {
+        match c {
+            '$' => {
+                x = 0;
+                y += 1;
+            }
+            '.' | '*' if x < 8 && y < 8 => {
+                cells[x + y * 8] = (c == '*') as u8;
+                x += 1;
+            }
+            '.' | '*' => {
+                return Err(error_at(line_number, idx + 1, "leaf is larger than 8x8"));
+            }
+            _ => {
+                return Err(error_at(
+                    line_number,
+                    idx + 1,
+                    &format!("expected '.', '*' or '$', found '{}'", c),
+                ))
+            }
+        }
+    }
+    Ok(MacrocellNode::Leaf { level: 3, cells })
+}
+
+fn parse_macrocell_inner(
+    line: &str,
+    line_number: usize,
+    nodes: &[MacrocellNode],
+) -> Result<MacrocellNode, String> {
+    let tokens = tokens(line);
+    if tokens.len() != 5 {
+        return Err(error_at(
+            line_number,
+            1,
+            "expected a level followed by four children",
+        ));
+    }
+    let number = |(column, token): (usize, &str)| {
+        token
+            .parse::<usize>()
+            .map_err(|_| error_at(line_number, column, &format!("'{}' is not a number", token)))
+    };
+    let level = number(tokens[0])?;
+    if !(1..=MACROCELL_MAX_LEVEL as usize).contains(&level) {
+        return Err(error_at(
+            line_number,
+            tokens[0].0,
+            &format!("level must be between 1 and {}", MACROCELL_MAX_LEVEL),
+        ));
+    }
+    let mut values = [0usize; 4];
+    for (value, &token) in values.iter_mut().zip(&tokens[1..]) {
+        *value = number(token)?;
+    }
+
+    if level == 1 {
+        let cells = values
+            .iter()
+            .zip(&tokens[1..])
+            .map(|(&state, &(column, _))| {
+                u8::try_from(state)
+                    .map_err(|_| error_at(line_number, column, "state must be at most 255"))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        return Ok(MacrocellNode::Leaf { level: 1, cells });
+    }
+    for (&child, &(column, _)) in values.iter().zip(&tokens[1..]) {
+        match nodes.get(child.wrapping_sub(1)) {
+            _ if child == 0 => {}
+            Some(node) if node.level() == level as u32 - 1 => {}
+            Some(_) => {
+                return Err(error_at(
+                    line_number,
+                    column,
+                    &format!("node {} is not at level {}", child, level - 1),
+                ))
+            }
+            None => {
+                return Err(error_at(
+                    line_number,
+                    column,
+                    &format!("node {} is not defined yet", child),
+                ))
+            }
+        }
+    }
+    Ok(MacrocellNode::Inner {
+        level: level as u32,
+        children: values,
+    })
+}
+
+// Whitespace-separated tokens with their 1-based columns.
+fn tokens(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (idx, c) in line
+        .char_indices()
+        .chain(std::iter::once((line.len(), ' ')))
+    {
+        match (c.is_whitespace(), start) {
+            (true, Some(token_start)) => {
+                tokens.push((token_start + 1, &line[token_start..idx]));
+                start = None;
+            }
+            (false, None) => start = Some(idx),
+            _ => {}
+        }
+    }
+    tokens
+}
+
+fn macrocell_bounds(node: &MacrocellNode, bounds: &[Bounds]) -> Bounds {
+    let union = |a: Bounds, b: Bounds| match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))),
+        (a, None) => a,
+        (None, b) => b,
+    };
+    match node {
+        MacrocellNode::Leaf { level, cells } => {
+            let size = 1usize << level;
+            cells
+                .iter()
+                .enumerate()
+                .filter(|(_, &state)| state != 0)
+                .map(|(idx, _)| {
+                    let (x, y) = ((idx % size) as u64, (idx / size) as u64);
+                    Some((x, y, x, y))
+                })
+                .fold(None, union)
+        }
+        MacrocellNode::Inner { level, children } => {
+            let half = 1u64 << (level - 1);
+            children
+                .iter()
+                .enumerate()
+                .map(|(quadrant, &child)| {
+                    let (dx, dy) = ((quadrant as u64 % 2) * half, (quadrant as u64 / 2) * half);
+                    bounds[child].map(|(x0, y0, x1, y1)| (x0 + dx, y0 + dy, x1 + dx, y1 + dy))
+                })
+                .fold(None, union)
+        }
+    }
+}
+
+// Node `id` has its top-left corner at (x, y) in pattern coordinates, which may be negative
+// when only part of it is inside the bounding box.
+fn paint_macrocell(
+    nodes: &[MacrocellNode],
+    bounds: &[Bounds],
+    id: usize,
+    x: i64,
+    y: i64,
+    pattern: &mut Pattern,
+) {
+    if bounds[id].is_none() {
+        return;
+    }
+    match &nodes[id - 1] {
+        MacrocellNode::Leaf { level, cells } => {
+            let size = 1usize << level;
+            for (idx, &state) in cells.iter().enumerate() {
+                if state != 0 {
+                    let cell_x = (x + (idx % size) as i64) as usize;
+                    let cell_y = (y + (idx / size) as i64) as usize;
+                    pattern.cells[cell_x + cell_y * pattern.width] = state;
+                }
+            }
+        }
+        MacrocellNode::Inner { level, children } => {
+            let half = 1i64 << (level - 1);
+            for (quadrant, &child) in children.iter().enumerate() {
+                let child_x = x + (quadrant as i64 % 2) * half;
+                let child_y = y + (quadrant as i64 / 2) * half;
+                paint_macrocell(nodes, bounds, child, child_x, child_y, pattern);
+            }
+        }
+    }
+}
+
+pub fn write_macrocell(pattern: &Pattern) -> String {
+    let multi_state = pattern.cells.iter().any(|&state| state > 1);
+    let leaf_level = if multi_state { 1 } else { 3 };
+    let mut level = leaf_level;
+    while (1usize << level) < pattern.width.max(pattern.height) {
+        level += 1;
+    }
+
+    let mut lines = Vec::new();
+    let mut ids = HashMap::new();
+    macrocell_node(pattern, 0, 0, level, leaf_level, &mut lines, &mut ids);
+
+    let mut out = "[M2] (cellular-automata)\n".to_string();
+    if let Some(rule) = &pattern.rule {
+        out.push_str(&format!("#R {}\n", rule));
+    }
+    for line in lines {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+// Identical nodes are written once and shared, which is what keeps large sparse or
+// repetitive patterns small.
+fn macrocell_node(
+    pattern: &Pattern,
+    x: usize,
+    y: usize,
+    level: u32,
+    leaf_level: u32,
+    lines: &mut Vec<String>,
+    ids: &mut HashMap<String, usize>,
+) -> usize {
+    if x >= pattern.width || y >= pattern.height {
+        return 0;
+    }
+    let size = 1usize << level;
+    let state = |cell_x: usize, cell_y: usize| {
+        if cell_x < pattern.width && cell_y < pattern.height {
+            pattern.cells[cell_x + cell_y * pattern.width]
+        } else {
+            0
+        }
+    };
+
+    let line = if level == leaf_level {
+        let states: Vec<u8> = (0..size * size)
+            .map(|idx| state(x + idx % size, y + idx / size))
+            .collect();
+        if states.iter().all(|&s| s == 0) {
+            return 0;
+        }
+        if level == 1 {
+            format!("1 {} {} {} {}", states[0], states[1], states[2], states[3])
+        } else {
+            let mut rows: Vec<String> = states
+                .chunks(size)
+                .map(|row| {
+                    let end = row.iter().rposition(|&s| s != 0).map_or(0, |idx| idx + 1);
+                    row[..end]
+                        .iter()
+                        .map(|&s| if s == 0 { '.' } else { '*' })
+                        .collect()
+                })
+                .collect();
+            while rows.last().is_some_and(|row| row.is_empty()) {
+                rows.pop();
+            }
+            rows.iter().map(|row| format!("{}$", row)).collect()
+        }
+    } else {
+        let half = size / 2;
+        let children = [(0, 0), (half, 0), (0, half), (half, half)].map(|(dx, dy)| {
+            macrocell_node(pattern, x + dx, y + dy, level - 1, leaf_level, lines, ids)
+        });
+        if children == [0; 4] {
+            return 0;
+        }
+        format!(
+            "{} {} {} {} {}",
+            level, children[0], children[1], children[2], children[3]
+        )
+    };
+    if let Some(&id) = ids.get(&line) {
+        return id;
+    }
+    lines.push(line.clone());
+    ids.insert(line, lines.len());
+    lines.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(width: usize, height: usize, cells: &[u8]) -> Pattern {
+        Pattern {
+            width,
+            height,
+            cells: cells.to_vec(),
+            rule: None,
+        }
+    }
+
+    fn glider() -> Pattern {
+        Pattern {
+            rule: Some("B3/S23".to_string()),
+            ..pattern(3, 3, &[0, 1, 0, 0, 0, 1, 1, 1, 1])
+        }
+    }
+
+    #[test]
+    fn rle_reads_golly_output() {
+        let text = "#N Glider\n#C A comment\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+        assert_eq!(parse_rle(text).unwrap(), glider());
+
+        let text = "x = 4, y = 2, rule = B2/S/C3\n.A2B$pA.A!";
+        let parsed = parse_rle(text).unwrap();
+        assert_eq!(parsed.cells, [0, 1, 2, 2, 25, 0, 1, 0]);
+        assert_eq!(parsed.rule.as_deref(), Some("B2/S/C3"));
+    }
+
+    #[test]
+    fn rle_round_trips() {
+        assert_eq!(parse_rle(&write_rle(&glider())).unwrap(), glider());
+
+        let states: Vec<u8> = (0..=255).collect();
+        let wide = pattern(64, 4, &states);
+        assert_eq!(parse_rle(&write_rle(&wide)).unwrap(), wide);
+
+        let sparse = pattern(
+            5,
+            4,
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        );
+        assert_eq!(parse_rle(&write_rle(&sparse)).unwrap(), sparse);
+    }
+
+    #[test]
+    fn placing_clips_to_the_board() {
+        let mut automata = Automata::new(4, 3, 3, 1, 0.0);
+        glider().place(&mut automata, 2, 1);
+        assert_eq!(automata.cells(), [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+
+        for (x, y) in [
+            (usize::MAX, 0),
+            (0, usize::MAX),
+            (usize::MAX - 1, usize::MAX - 1),
+        ] {
+            glider().place(&mut automata, x, y);
+        }
+        assert_eq!(automata.cells(), [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rle_errors_give_line_and_column() {
+        let cases = [
+            ("bo$2bo$3o!", "line 1, column 1"),
+            ("#C\nx = 3, y = 3\nbo$2bo$3o?!", "line 3, column 10"),
+            ("x = 3, y = 3\nbo$4o!", "line 2, column 5"),
+            ("x = 3, y = 1\nbo$o!", "line 2, column 4"),
+            (
+                "x = 3, y = 3\n99999999999999999999999o!",
+                "line 2, column 20",
+            ),
+            ("x = 99999, y = 99999\n!", "line 1, column 1"),
+            ("x = 18446744073709551615, y = 2\n!", "line 1, column 1"),
+            ("x = 3, y = 3\nbo$2bo$3Z!", "line 2, column 9"),
+        ];
+        for (text, location) in cases {
+            let err = parse_rle(text).unwrap_err();
+            assert!(err.starts_with(location), "{:?}: {}", text, err);
+        }
+        assert!(parse_rle("").is_err());
+    }
+
+    #[test]
+    fn cells_reads_lifewiki_files() {
+        let text = "!Name: Glider\n!\n.O\n..O\nOOO\n";
+        let parsed = parse_cells(text).unwrap();
+        assert_eq!(parsed.cells, glider().cells);
+        assert_eq!((parsed.width, parsed.height), (3, 3));
+    }
+
+    #[test]
+    fn cells_round_trips() {
+        let plain = Pattern {
+            rule: None,
+            ..glider()
+        };
+        assert_eq!(parse_cells(&write_cells(&plain).unwrap()).unwrap(), plain);
+        let dead_edges = pattern(4, 3, &[0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            parse_cells(&write_cells(&dead_edges).unwrap()).unwrap(),
+            dead_edges
+        );
+    }
+
+    #[test]
+    fn cells_errors_give_line_and_column() {
+        let err = parse_cells("!Name: x\n.O\n.Ox\n").unwrap_err();
+        assert!(err.starts_with("line 3, column 3"), "{}", err);
+        assert!(write_cells(&pattern(2, 1, &[1, 2])).is_err());
+    }
+
+    #[test]
+    fn macrocell_reads_golly_files() {
+        let text = "[M2] (golly 2.0)\n#R B3/S23\n.*$..*$***$\n";
+        assert_eq!(parse_macrocell(text).unwrap(), glider());
+
+        // Two 8x8 leaves in opposite corners of a 16x16 node.
+        let text = "[M2] (golly 2.0)\n*$\n$$$$$$$.......*$\n4 1 0 0 2\n";
+        let parsed = parse_macrocell(text).unwrap();
+        assert_eq!((parsed.width, parsed.height), (16, 16));
+        assert_eq!(parsed.cells.iter().filter(|&&state| state != 0).count(), 2);
+        assert_eq!(parsed.cells[0], 1);
+        assert_eq!(parsed.cells[15 + 15 * 16], 1);
+    }
+
+    #[test]
+    fn macrocell_round_trips() {
+        assert_eq!(
+            parse_macrocell(&write_macrocell(&glider())).unwrap(),
+            glider()
+        );
+
+        let mut cells = vec![0; 100 * 70];
+        cells[0] = 1;
+        cells[99 + 69 * 100] = 1;
+        cells[50 + 30 * 100] = 1;
+        let sparse = pattern(100, 70, &cells);
+        assert_eq!(parse_macrocell(&write_macrocell(&sparse)).unwrap(), sparse);
+
+        let multi_state = pattern(3, 2, &[1, 0, 7, 0, 255, 2]);
+        assert_eq!(
+            parse_macrocell(&write_macrocell(&multi_state)).unwrap(),
+            multi_state
+        );
+
+        // Only live cells are kept, so an empty board comes back as an empty pattern.
+        let empty = parse_macrocell(&write_macrocell(&pattern(5, 5, &[0; 25]))).unwrap();
+        assert_eq!((empty.width, empty.height), (0, 0));
+        assert!(empty.cells.is_empty());
+    }
+
+    #[test]
+    fn macrocell_errors_give_line_and_column() {
+        let cases = [
+            ("#R B3/S23\n", "line 1, column 1"),
+            ("[M2]\n.*$\n4 0 0 0 2\n", "line 3, column 9"),
+            ("[M2]\n.*$\n.*$\n5 1 2 0 0\n", "line 4, column 3"),
+            ("[M2]\n.*x$\n", "line 2, column 3"),
+            ("[M2]\n1 0 1 256 0\n", "line 2, column 7"),
+            ("[M2]\n63 0 0 0 0\n", "line 2, column 1"),
+        ];
+        for (text, location) in cases {
+            let err = parse_macrocell(text).unwrap_err();
+            assert!(err.starts_with(location), "{:?}: {}", text, err);
+        }
+        assert!(parse_macrocell("").is_err());
+    }
+}
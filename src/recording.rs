@@ -0,0 +1,218 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::snapshot::{check_scale, upscale};
+
+// Both formats are written frame by frame as the run goes. APNG needs the frame count in its
+// header, so the maximum length goes there and is patched to the real count on finish.
+enum Sink {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Apng(png::Writer<BufWriter<File>>),
+}
+
+pub struct Recording {
+    path: String,
+    width: usize,
+    height: usize,
+    scale: usize,
+    delay_ms: u16,
+    max_frames: usize,
+    frames: usize,
+    sink: Sink,
+}
+
+impl Recording {
+    // The format follows the extension: `.gif`, or `.png` / `.apng` for APNG. Frames are
+    // `width` x `height` RGBA images before scaling.
+    pub fn start(
+        path: &str,
+        width: usize,
+        height: usize,
+        scale: usize,
+        delay_ms: u16,
+        max_frames: usize,
+    ) -> Result<Self, String> {
+        check_scale(width, height, scale)?;
+        if max_frames == 0 {
+            return Err("maximum length must be at least 1 frame".to_string());
+        }
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let sink = match extension.as_str() {
+            "gif" => {
+                let (scaled_w, scaled_h) = (width * scale, height * scale);
+                if scaled_w > u16::MAX as usize || scaled_h > u16::MAX as usize {
+                    return Err(format!("GIF frames are at most {0}x{0}", u16::MAX));
+                }
+                let file = File::create(path)
+                    .map_err(|err| format!("could not write {}: {}", path, err))?;
+                let mut encoder =
+                    gif::Encoder::new(BufWriter::new(file), scaled_w as u16, scaled_h as u16, &[])
+                        .map_err(|err| format!("could not write {}: {}", path, err))?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(|err| format!("could not write {}: {}", path, err))?;
+                Sink::Gif(encoder)
+            }
+            "png" | "apng" => {
+                let error =
+                    |err: &dyn std::fmt::Display| format!("could not write {}: {}", path, err);
+                let file = File::create(path).map_err(|err| error(&err))?;
+                let mut encoder = png::Encoder::new(
+                    BufWriter::new(file),
+                    (width * scale) as u32,
+                    (height * scale) as u32,
+                );
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder
+                    .set_animated(max_frames.min(u32::MAX as usize) as u32, 0)
+                    .and_then(|()| encoder.set_frame_delay(delay_ms, 1000))
+                    .map_err(|err| error(&err))?;
+                Sink::Apng(encoder.write_header().map_err(|err| error(&err))?)
+            }
+            _ => return Err("record to a .gif, .png or .apng file".to_string()),
+        };
+        Ok(Self {
+            path: path.to_string(),
+            width,
+            height,
+            scale,
+            delay_ms,
+            max_frames,
+            frames: 0,
+            sink,
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.frames >= self.max_frames
+    }
+
+    // Frames past the maximum length are dropped.
+    pub fn add_frame(&mut self, rgba: &[u8]) -> Result<(), String> {
+        if self.is_full() {
+            return Ok(());
+        }
+        let mut scaled = upscale(self.width, self.height, rgba, self.scale);
+        let error = |err: &dyn std::fmt::Display| format!("could not write {}: {}", self.path, err);
+        match &mut self.sink {
+            Sink::Gif(encoder) => {
+                let (width, height) = (
+                    (self.width * self.scale) as u16,
+                    (self.height * self.scale) as u16,
+                );
+                let mut frame = match palette_frame(&scaled) {
+                    Some((indices, palette)) => {
+                        gif::Frame::from_palette_pixels(width, height, indices, palette, None)
+                    }
+                    None => gif::Frame::from_rgba_speed(width, height, &mut scaled, 10),
+                };
+                // GIF delays are in hundredths of a second.
+                frame.delay = self.delay_ms.div_ceil(10);
+                encoder.write_frame(&frame).map_err(|err| error(&err))?;
+            }
+            Sink::Apng(writer) => writer
+                .write_image_data(&scaled)
+                .map_err(|err| error(&err))?,
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Closes the file; a GIF gets its trailer and an APNG its end chunk and real frame count.
+    pub fn finish(self) -> Result<usize, String> {
+        let error = |err: &dyn std::fmt::Display| format!("could not write {}: {}", self.path, err);
+        match self.sink {
+            Sink::Gif(encoder) => {
+                encoder.into_inner().map_err(|err| error(&err))?;
+            }
+            Sink::Apng(writer) => {
+                writer.finish().map_err(|err| error(&err))?;
+                if self.frames == 0 {
+                    let _ = fs::remove_file(&self.path);
+                    return Err("no generations were recorded".to_string());
+                }
+                patch_frame_count(&self.path, self.frames as u32).map_err(|err| error(&err))?;
+            }
+        }
+        Ok(self.frames)
+    }
+}
+
+// Rewrites the frame count in the `acTL` chunk, which sits among the header chunks before the
+// first image data, and the chunk's CRC.
+fn patch_frame_count(path: &str, frames: u32) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    let mut position = 8; // PNG signature
+    loop {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        match &header[4..] {
+            b"acTL" => break,
+            b"IDAT" => return Err(std::io::Error::other("no acTL chunk before the image data")),
+            _ => position += 12 + length,
+        }
+    }
+    let mut chunk = [0u8; 12];
+    file.seek(SeekFrom::Start(position + 4))?;
+    file.read_exact(&mut chunk)?;
+    chunk[4..8].copy_from_slice(&frames.to_be_bytes());
+    file.seek(SeekFrom::Start(position + 4))?;
+    file.write_all(&chunk)?;
+    file.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+// The CRC-32 PNG chunks end with, over the chunk type and data.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// Boards rarely use more than a handful of colors, so most frames can be stored exactly with a
+// palette instead of going through color quantization.
+fn palette_frame(rgba: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+    let mut last = None;
+    for pixel in rgba.chunks(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        if let Some((last_color, index)) = last {
+            if last_color == color {
+                indices.push(index);
+                continue;
+            }
+        }
+        let index = match colors.iter().position(|&known| known == color) {
+            Some(index) => index,
+            None if colors.len() < 256 => {
+                colors.push(color);
+                colors.len() - 1
+            }
+            None => return None,
+        };
+        last = Some((color, index as u8));
+        indices.push(index as u8);
+    }
+    Some((indices, colors.concat()))
+}
@@ -0,0 +1,701 @@
+use std::collections::HashMap;
+
+use crate::config::MAX_STATES;
+use crate::neighborhood::Neighborhood;
+use crate::rules::{max_cells, RulesCollection};
+
+// Neighbors in the order @TABLE transitions list them after the centre cell, clockwise from
+// north.
+const MOORE: [(isize, isize); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+const VON_NEUMANN: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+const HEXAGONAL: [(isize, isize); 6] = [(0, -1), (1, 0), (1, 1), (0, 1), (-1, 0), (-1, -1)];
+const ONE_DIMENSIONAL: [(isize, isize); 2] = [(-1, 0), (1, 0)];
+// @TREE files branch on the neighbors in this order and on the centre cell last.
+const TREE_MOORE: [(isize, isize); 8] = [
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+    (0, -1),
+    (-1, 0),
+    (1, 0),
+    (0, 1),
+];
+const TREE_VON_NEUMANN: [(isize, isize); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+
+type Neighbors = &'static [(isize, isize)];
+
+fn error_at(line: usize, message: &str) -> String {
+    format!("line {}: {}", line, message)
+}
+
+// Golly's names for the neighborhoods it shares with us.
+fn golly_neighborhoods() -> [(&'static str, Neighborhood, Neighbors); 4] {
+    [
+        ("Moore", Neighborhood::Moore(1), &MOORE),
+        ("vonNeumann", Neighborhood::VonNeumann(1), &VON_NEUMANN),
+        ("hexagonal", Neighborhood::Hexagonal, &HEXAGONAL),
+        (
+            "oneDimensional",
+            Neighborhood::Rectangle {
+                width: 3,
+                height: 1,
+            },
+            &ONE_DIMENSIONAL,
+        ),
+    ]
+}
+
+// Reads the @TABLE or @TREE section of a Golly `.rule` file. Neighborhoods that match no
+// entry of a @TABLE leave the cell unchanged, as in Golly.
+pub fn parse_rule_file(text: &str) -> Result<RulesCollection, String> {
+    let mut section = None;
+    let mut lines = Vec::new();
+    for (line_idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if let Some(name) = line.strip_prefix('@') {
+            if section.is_some() {
+                break;
+            }
+            let name = name.split_whitespace().next().unwrap_or("");
+            if name == "TABLE" || name == "TREE" {
+                section = Some(name.to_string());
+            }
+            continue;
+        }
+        if section.is_some() && !line.is_empty() {
+            lines.push((line_idx + 1, line));
+        }
+    }
+    match section.as_deref() {
+        Some("TABLE") => parse_table(&lines),
+        Some("TREE") => parse_tree(&lines),
+        _ => Err("no @TABLE or @TREE section".to_string()),
+    }
+}
+
+// One position of a transition: the states it accepts, and the variable it binds if any.
+// Every use of the same variable within a transition must take the same state.
+struct Entry {
+    values: Vec<u8>,
+    variable: Option<usize>,
+}
+
+// Variables by name, with the index they are bound by and their states.
+type Variables = HashMap<String, (usize, Vec<u8>)>;
+
+struct Transition {
+    inputs: Vec<Entry>,
+    output: Entry,
+}
+
+fn parse_table(lines: &[(usize, &str)]) -> Result<RulesCollection, String> {
+    let mut states: Option<usize> = None;
+    let mut neighborhood: Option<(Neighborhood, Neighbors)> = None;
+    let mut symmetries = "none".to_string();
+    let mut variables = Variables::new();
+    let mut variable_count = 0;
+    let mut transitions = Vec::new();
+
+    for &(line_number, line) in lines {
+        if let Some(value) = line.strip_prefix("n_states:") {
+            let value = value.trim();
+            states = Some(
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|states| (2..=MAX_STATES).contains(states))
+                    .ok_or_else(|| {
+                        error_at(
+                            line_number,
+                            &format!("n_states must be between 2 and {}", MAX_STATES),
+                        )
+                    })?,
+            );
+        } else if let Some(value) = line.strip_prefix("neighborhood:") {
+            let value = value.trim();
+            let (_, shape, neighbors) = golly_neighborhoods()
+                .into_iter()
+                .find(|(name, _, _)| name.eq_ignore_ascii_case(value))
+                .ok_or_else(|| {
+                    error_at(
+                        line_number,
+                        &format!("unsupported neighborhood '{}'", value),
+                    )
+                })?;
+            neighborhood = Some((shape, neighbors));
+        } else if let Some(value) = line.strip_prefix("symmetries:") {
+            symmetries = value.trim().to_string();
+        } else if let Some(definition) = line.strip_prefix("var ") {
+            let states = states.ok_or_else(|| error_at(line_number, "n_states must come first"))?;
+            let (name, values) = definition
+                .split_once('=')
+                .ok_or_else(|| error_at(line_number, "expected 'var name={...}'"))?;
+            // A bare name copies another variable's states, as in `var b=a`.
+            let values = parse_entry(values.trim(), states, &variables, line_number)?.values;
+            // A redefined name gets a fresh index, so indices never repeat.
+            variables.insert(name.trim().to_string(), (variable_count, values));
+            variable_count += 1;
+        } else {
+            let states = states.ok_or_else(|| error_at(line_number, "n_states must come first"))?;
+            let (_, neighbors) = neighborhood
+                .as_ref()
+                .ok_or_else(|| error_at(line_number, "neighborhood must come first"))?;
+            transitions.push(parse_transition(
+                line,
+                line_number,
+                states,
+                neighbors.len(),
+                &variables,
+            )?);
+        }
+    }
+
+    let states = states.ok_or("missing n_states")?;
+    let (shape, neighbors) = neighborhood.ok_or("missing neighborhood")?;
+    if shape.cell_count() > max_cells(states) {
+        return Err(format!(
+            "{} with {} states has more patterns than the rule table can hold",
+            shape.label(),
+            states
+        ));
+    }
+    let permutations = symmetry_permutations(&symmetries, neighbors.len())?;
+
+    let mut rules = RulesCollection::with_states(shape, states);
+    let positions = golly_positions(&rules, neighbors);
+    let mut inputs = vec![0u8; neighbors.len() + 1];
+    for key in 0..rules.patterns() {
+        let pattern = pattern_states(&rules, key);
+        for (input, &position) in inputs.iter_mut().zip(&positions) {
+            *input = pattern[position];
+        }
+        let output = transitions
+            .iter()
+            .find_map(|transition| match_transition(transition, &inputs, &permutations))
+            .unwrap_or(inputs[0]);
+        rules.set_new_cell(key, output);
+    }
+    Ok(rules)
+}
+
+fn parse_set(
+    text: &str,
+    states: usize,
+    variables: &Variables,
+    line_number: usize,
+) -> Result<Vec<u8>, String> {
+    let inner = text
+        .strip_prefix('{')
+        .and_then(|text| text.strip_suffix('}'))
+        .ok_or_else(|| error_at(line_number, &format!("'{}' is not a {{...}} set", text)))?;
+    let mut values = Vec::new();
+    for item in inner.split(',') {
+        let entry = parse_entry(item.trim(), states, variables, line_number)?;
+        values.extend(entry.values);
+    }
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+fn parse_entry(
+    token: &str,
+    states: usize,
+    variables: &Variables,
+    line_number: usize,
+) -> Result<Entry, String> {
+    if token.starts_with('{') {
+        return Ok(Entry {
+            values: parse_set(token, states, variables, line_number)?,
+            variable: None,
+        });
+    }
+    if let Ok(state) = token.parse::<usize>() {
+        if state >= states {
+            return Err(error_at(
+                line_number,
+                &format!("state {} is not below n_states ({})", state, states),
+            ));
+        }
+        return Ok(Entry {
+            values: vec![state as u8],
+            variable: None,
+        });
+    }
+    match variables.get(token) {
+        Some((index, values)) => Ok(Entry {
+            values: values.clone(),
+            variable: Some(*index),
+        }),
+        None => Err(error_at(
+            line_number,
+            &format!("'{}' is not a state or variable", token),
+        )),
+    }
+}
+
+// Transitions are comma separated, except that the commas may be left out when every entry
+// is a single digit.
+fn parse_transition(
+    line: &str,
+    line_number: usize,
+    states: usize,
+    neighbors: usize,
+    variables: &Variables,
+) -> Result<Transition, String> {
+    let tokens: Vec<String> = if line.contains(',') || line.contains('{') {
+        let mut tokens = vec![String::new()];
+        let mut depth = 0;
+        for c in line.chars() {
+            match c {
+                ',' if depth == 0 => tokens.push(String::new()),
+                _ => {
+                    depth += (c == '{') as i32 - (c == '}') as i32;
+                    if let Some(token) = tokens.last_mut() {
+                        token.push(c);
+                    }
+                }
+            }
+        }
+        tokens
+            .iter()
+            .map(|token| token.trim().to_string())
+            .collect()
+    } else {
+        line.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(String::from)
+            .collect()
+    };
+    if tokens.len() != neighbors + 2 {
+        return Err(error_at(
+            line_number,
+            &format!(
+                "expected {} entries (centre, {} neighbors, new state), found {}",
+                neighbors + 2,
+                neighbors,
+                tokens.len()
+            ),
+        ));
+    }
+
+    let mut inputs = tokens
+        .iter()
+        .map(|token| parse_entry(token, states, variables, line_number))
+        .collect::<Result<Vec<Entry>, String>>()?;
+    let output = inputs.pop().expect("length checked above");
+    let bound = |index: usize| inputs.iter().any(|input| input.variable == Some(index));
+    if output.values.len() > 1 && !output.variable.is_some_and(bound) {
+        return Err(error_at(
+            line_number,
+            "the new state must be a state or a variable used in the inputs",
+        ));
+    }
+    Ok(Transition { inputs, output })
+}
+
+// Each permutation maps a transition's neighbor position to the input neighbor it is
+// compared with. `None` means "permute": any order matches.
+fn symmetry_permutations(name: &str, neighbors: usize) -> Result<Option<Vec<Vec<usize>>>, String> {
+    if name == "permute" {
+        return Ok(None);
+    }
+    let rotations = match name.trim_end_matches("reflect") {
+        "none" | "" | "reflect_horizontal" => 1,
+        rotate => rotate
+            .strip_prefix("rotate")
+            .and_then(|count| count.parse::<usize>().ok())
+            .filter(|&count| count > 0 && neighbors.is_multiple_of(count) && neighbors > 2)
+            .ok_or_else(|| {
+                format!(
+                    "symmetries '{}' do not apply to a {}-neighbor rule",
+                    name, neighbors
+                )
+            })?,
+    };
+    let reflect = name.ends_with("reflect") || name == "reflect_horizontal";
+    let reflected = |i: usize| {
+        if neighbors == 2 {
+            1 - i
+        } else {
+            (neighbors - i) % neighbors
+        }
+    };
+
+    let mut permutations = Vec::new();
+    for rotation in 0..rotations {
+        let shift = rotation * neighbors / rotations;
+        let rotated: Vec<usize> = (0..neighbors).map(|i| (i + shift) % neighbors).collect();
+        if reflect {
+            permutations.push(rotated.iter().map(|&i| reflected(i)).collect());
+        }
+        permutations.push(rotated);
+    }
+    Ok(Some(permutations))
+}
+
+// `inputs` is the centre cell followed by the neighbors in Golly's order.
+fn match_transition(
+    transition: &Transition,
+    inputs: &[u8],
+    permutations: &Option<Vec<Vec<usize>>>,
+) -> Option<u8> {
+    let mut bindings = Vec::new();
+    let matched = match permutations {
+        Some(permutations) => permutations.iter().any(|permutation| {
+            bindings.clear();
+            entry_matches(&transition.inputs[0], inputs[0], &mut bindings)
+                && permutation.iter().enumerate().all(|(position, &neighbor)| {
+                    entry_matches(
+                        &transition.inputs[position + 1],
+                        inputs[neighbor + 1],
+                        &mut bindings,
+                    )
+                })
+        }),
+        None => {
+            let mut used = vec![false; inputs.len() - 1];
+            entry_matches(&transition.inputs[0], inputs[0], &mut bindings)
+                && match_any_order(
+                    &transition.inputs[1..],
+                    &inputs[1..],
+                    &mut used,
+                    &mut bindings,
+                )
+        }
+    };
+    if !matched {
+        return None;
+    }
+    match transition.output.variable {
+        Some(index) if transition.output.values.len() > 1 => bindings
+            .iter()
+            .find(|&&(bound, _)| bound == index)
+            .map(|&(_, state)| state),
+        _ => transition.output.values.first().copied(),
+    }
+}
+
+fn entry_matches(entry: &Entry, state: u8, bindings: &mut Vec<(usize, u8)>) -> bool {
+    if !entry.values.contains(&state) {
+        return false;
+    }
+    match entry.variable {
+        Some(index) => match bindings.iter().find(|&&(bound, _)| bound == index) {
+            Some(&(_, bound_state)) => bound_state == state,
+            None => {
+                bindings.push((index, state));
+                true
+            }
+        },
+        None => true,
+    }
+}
+
+// Backtracking search for an order of the neighbors that matches; neighbors with the same
+// state are interchangeable, so only the first unused one of each state is tried.
+fn match_any_order(
+    entries: &[Entry],
+    neighbors: &[u8],
+    used: &mut [bool],
+    bindings: &mut Vec<(usize, u8)>,
+) -> bool {
+    let Some((entry, rest)) = entries.split_first() else {
+        return true;
+    };
+    let mut tried = Vec::new();
+    for i in 0..neighbors.len() {
+        if used[i] || tried.contains(&neighbors[i]) {
+            continue;
+        }
+        tried.push(neighbors[i]);
+        let bound = bindings.len();
+        if entry_matches(entry, neighbors[i], bindings) {
+            used[i] = true;
+            if match_any_order(rest, neighbors, used, bindings) {
+                return true;
+            }
+            used[i] = false;
+        }
+        bindings.truncate(bound);
+    }
+    false
+}
+
+fn parse_tree(lines: &[(usize, &str)]) -> Result<RulesCollection, String> {
+    let mut states = None;
+    let mut neighbors = None;
+    let mut nodes: Vec<(usize, Vec<usize>)> = Vec::new();
+    for &(line_number, line) in lines {
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| error_at(line_number, &format!("'{}' is not a number", value)))?;
+            match key.trim() {
+                "num_states" if (2..=MAX_STATES).contains(&value) => states = Some(value),
+                "num_states" => {
+                    return Err(error_at(
+                        line_number,
+                        &format!("num_states must be between 2 and {}", MAX_STATES),
+                    ))
+                }
+                "num_neighbors" if value == 4 || value == 8 => neighbors = Some(value),
+                "num_neighbors" => {
+                    return Err(error_at(line_number, "num_neighbors must be 4 or 8"))
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let states = states.ok_or_else(|| error_at(line_number, "num_states must come first"))?;
+        let numbers = line
+            .split_whitespace()
+            .map(|token| {
+                token
+                    .parse::<usize>()
+                    .map_err(|_| error_at(line_number, &format!("'{}' is not a number", token)))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        let (&level, children) = numbers
+            .split_first()
+            .ok_or_else(|| error_at(line_number, "empty node"))?;
+        if children.len() != states {
+            return Err(error_at(
+                line_number,
+                &format!("a node needs a level and {} entries", states),
+            ));
+        }
+        for &child in children {
+            let valid = match level {
+                0 => false,
+                1 => child < states,
+                _ => nodes
+                    .get(child)
+                    .is_some_and(|(child_level, _)| *child_level == level - 1),
+            };
+            if !valid {
+                return Err(error_at(
+                    line_number,
+                    &format!("entry {} is not valid for a level {} node", child, level),
+                ));
+            }
+        }
+        nodes.push((level, children.to_vec()));
+    }
+
+    let states = states.ok_or("missing num_states")?;
+    let neighbors = neighbors.ok_or("missing num_neighbors")?;
+    let root = nodes.len().checked_sub(1).ok_or("tree has no nodes")?;
+    if nodes[root].0 != neighbors + 1 {
+        return Err(format!("the last node must be at level {}", neighbors + 1));
+    }
+    let (shape, order): (Neighborhood, Neighbors) = if neighbors == 8 {
+        (Neighborhood::Moore(1), &TREE_MOORE)
+    } else {
+        (Neighborhood::VonNeumann(1), &TREE_VON_NEUMANN)
+    };
+    if shape.cell_count() > max_cells(states) {
+        return Err(format!(
+            "{} with {} states has more patterns than the rule table can hold",
+            shape.label(),
+            states
+        ));
+    }
+
+    let mut rules = RulesCollection::with_states(shape, states);
+    let mut positions = golly_positions(&rules, order);
+    positions.rotate_left(1);
+    for key in 0..rules.patterns() {
+        let pattern = pattern_states(&rules, key);
+        let output = positions.iter().fold(root, |node, &position| {
+            nodes[node].1[pattern[position] as usize]
+        });
+        rules.set_new_cell(key, output as u8);
+    }
+    Ok(rules)
+}
+
+// Index into the rule's own offsets of the centre cell followed by each of `neighbors`.
+fn golly_positions(rules: &RulesCollection, neighbors: &[(isize, isize)]) -> Vec<usize> {
+    let offsets = rules.neighborhood().offsets();
+    std::iter::once(&(0, 0))
+        .chain(neighbors)
+        .map(|offset| {
+            offsets
+                .iter()
+                .position(|o| o == offset)
+                .expect("Golly neighborhoods match our offsets")
+        })
+        .collect()
+}
+
+// States of each neighborhood cell, in offset order, for a pattern key.
+fn pattern_states(rules: &RulesCollection, mut key: u64) -> Vec<u8> {
+    let states = rules.states() as u64;
+    (0..rules.cell_count())
+        .map(|_| {
+            let state = (key % states) as u8;
+            key /= states;
+            state
+        })
+        .collect()
+}
+
+// Writes the rule as a @TABLE with one transition per pattern that changes the centre cell.
+pub fn write_rule_file(rules: &RulesCollection, name: &str) -> Result<String, String> {
+    let offsets = rules.neighborhood().offsets();
+    let (golly_name, _, neighbors) = golly_neighborhoods()
+        .into_iter()
+        .find(|(_, shape, _)| shape.offsets() == offsets)
+        .ok_or_else(|| {
+            format!(
+                "Golly has no {} neighborhood; use Moore, von Neumann, hexagonal or 3x1",
+                rules.neighborhood().label()
+            )
+        })?;
+
+    let mut out = format!(
+        "@RULE {}\n\n@TABLE\nn_states:{}\nneighborhood:{}\nsymmetries:none\n\n",
+        name,
+        rules.states(),
+        golly_name
+    );
+    let positions = golly_positions(rules, neighbors);
+    for key in 0..rules.patterns() {
+        let pattern = pattern_states(rules, key);
+        let centre = pattern[positions[0]];
+        let output = rules.get_new_cell(key);
+        if output == centre {
+            continue;
+        }
+        let inputs: Vec<String> = positions
+            .iter()
+            .map(|&position| pattern[position].to_string())
+            .collect();
+        out.push_str(&format!("{},{}\n", inputs.join(","), output));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::Automata;
+    use num_bigint::BigUint;
+
+    const LIFE_TABLE: &str = "@RULE Life
+
+# Conway's Life as Golly's Rule Table Repository has it.
+@TABLE
+n_states:2
+neighborhood:Moore
+symmetries:permute
+var a={0,1}
+var b={0,1}
+var c={0,1}
+var d={0,1}
+var e={0,1}
+var f={0,1}
+var g={0,1}
+var h={0,1}
+0,1,1,1,0,0,0,0,0,1
+1,1,1,0,0,0,0,0,0,1
+1,1,1,1,0,0,0,0,0,1
+1,a,b,c,d,e,f,g,h,0
+
+@COLORS
+1 255 255 255
+";
+
+    #[test]
+    fn golly_life_table_is_b3_s23() {
+        let rules = parse_rule_file(LIFE_TABLE).unwrap();
+        let life = RulesCollection::from_rulestring("B3/S23").unwrap();
+        assert_eq!(rules.wolfram_code(), life.wolfram_code());
+    }
+
+    #[test]
+    fn one_dimensional_table_matches_wolfram_numbering() {
+        let text = "@TABLE
+n_states:2
+neighborhood:oneDimensional
+symmetries:none
+var a={0,1}
+a,1,0,1
+a,0,1,1
+a,0,0,0
+a,1,1,0
+";
+        let rules = parse_rule_file(text).unwrap();
+        assert_eq!(rules.wolfram_code(), BigUint::from(90u32));
+    }
+
+    #[test]
+    fn von_neumann_tree() {
+        // The new state is the centre XOR its north neighbor. Levels branch on N, W, E, S
+        // and the centre last.
+        let text = "@TREE
+num_states=2
+num_neighbors=4
+num_nodes=9
+1 0 1
+1 1 0
+2 0 0
+2 1 1
+3 2 2
+3 3 3
+4 4 4
+4 5 5
+5 6 7
+";
+        let rules = parse_rule_file(text).unwrap();
+        let mut automata = Automata::with_neighborhood(11, 7, Neighborhood::VonNeumann(1), 0.5);
+        automata.set_rules(rules);
+        let board = automata.cells();
+        automata.step();
+        let expected: Vec<u8> = (0..board.len())
+            .map(|idx| board[idx] ^ board[(idx + board.len() - 11) % board.len()])
+            .collect();
+        assert_eq!(automata.cells(), expected);
+    }
+
+    #[test]
+    fn written_tables_read_back() {
+        let life = RulesCollection::from_rulestring("B36/S23").unwrap();
+        let text = write_rule_file(&life, "HighLife").unwrap();
+        assert_eq!(
+            parse_rule_file(&text).unwrap().wolfram_code(),
+            life.wolfram_code()
+        );
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        let cases = [
+            ("@TABLE\nn_states:2\nneighborhood:Moore\n0,1,2\n", "line 4:"),
+            ("@TABLE\nn_states:40\n", "line 2:"),
+            (
+                "@TABLE\nn_states:2\nneighborhood:Moore\nvar a={0,1}\n0,0,0,0,0,0,0,0,0,a\n",
+                "line 5:",
+            ),
+            ("@TREE\nnum_states=2\nnum_neighbors=4\n1 0 2\n", "line 4:"),
+        ];
+        for (text, line) in cases {
+            let err = parse_rule_file(text).err().unwrap_or_default();
+            assert!(err.starts_with(line), "{:?}: {}", text, err);
+        }
+        assert!(parse_rule_file("@RULE Nothing\n").is_err());
+    }
+}
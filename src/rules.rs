@@ -1,53 +1,819 @@
-use ::rand::{rngs::SmallRng, Rng};
-use std::collections::HashMap;
+use ::rand::{rngs::SmallRng, seq::index, Rng};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
-use crate::config::MAX_NEIGHBORHOOD_BITS;
+use crate::config::{MAX_NEIGHBORHOOD_BITS, MAX_STATES};
+use crate::neighborhood::Neighborhood;
+use crate::rulestring::{parse_life_like, LifeLikeRule};
 
+const MAX_PATTERNS: u64 = 1 << MAX_NEIGHBORHOOD_BITS;
+
+// How random tables are drawn. With `lambda` set, each output is non-zero with that
+// probability (Langton's lambda), or exactly that fraction of outputs is non-zero when
+// `exact_lambda` is set; without it every state is equally likely. `quiescent` forces the
+// all-zero neighborhood to map to zero.
+//
+// The symmetry fields make the table invariant under mirroring the neighborhood left-right
+// or up-down and under quarter turns, or make the output depend only on state sums. One
+// output is drawn per class of patterns the symmetry maps onto each other; exact lambda
+// still counts patterns, picking classes whose sizes add up to it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuleOptions {
+    pub lambda: Option<f64>,
+    pub exact_lambda: bool,
+    pub quiescent: bool,
+    pub mirror_x: bool,
+    pub mirror_y: bool,
+    pub rotate: bool,
+    pub totalism: Totalism,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Totalism {
+    #[default]
+    Off,
+    // The sum of all cell states.
+    Totalistic,
+    // The centre cell's state and the sum of the others.
+    OuterTotalistic,
+}
+
+impl Totalism {
+    pub const ALL: [Totalism; 3] = [
+        Totalism::Off,
+        Totalism::Totalistic,
+        Totalism::OuterTotalistic,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Totalism::Off => "Any",
+            Totalism::Totalistic => "Totalistic",
+            Totalism::OuterTotalistic => "Outer totalistic",
+        }
+    }
+}
+
+// One output per neighborhood pattern, packed into words with 1, 2 or 4 bits per output
+// depending on the number of states, so a binary 16-cell neighborhood costs 8 KiB and a
+// lookup is a shift and a mask. Patterns are indexed in base `states`, first cell lowest.
 #[derive(Clone)]
 pub struct RulesCollection {
-    hash_map: HashMap<u64, u8>,
-    neighborhood_w: usize,
-    neighborhood_h: usize,
+    table: Vec<u64>,
+    neighborhood: Neighborhood,
+    cell_count: usize,
+    states: usize,
+    patterns: u64,
+    entry_bits: u64,
 }
 
 impl RulesCollection {
     pub fn new(neighborhood_w: usize, neighborhood_h: usize) -> Self {
+        Self::with_neighborhood(Neighborhood::Rectangle {
+            width: neighborhood_w,
+            height: neighborhood_h,
+        })
+    }
+
+    pub fn with_neighborhood(neighborhood: Neighborhood) -> Self {
+        Self::with_states(neighborhood, 2)
+    }
+
+    pub fn with_states(neighborhood: Neighborhood, states: usize) -> Self {
+        assert!(
+            (2..=MAX_STATES).contains(&states),
+            "states must be between 2 and {}",
+            MAX_STATES
+        );
+        let cell_count = neighborhood.cell_count();
+        assert!(
+            cell_count <= max_cells(states),
+            "neighborhood too big for u64 hash"
+        );
+        let patterns = (states as u64).pow(cell_count as u32);
+        let entry_bits = match states {
+            2 => 1,
+            3..=4 => 2,
+            _ => 4,
+        };
         Self {
-            hash_map: HashMap::new(),
-            neighborhood_w,
-            neighborhood_h,
+            table: vec![0u64; (patterns * entry_bits).div_ceil(64) as usize],
+            neighborhood,
+            cell_count,
+            states,
+            patterns,
+            entry_bits,
         }
     }
 
-    pub fn set_neighborhood_size(
+    // Digit `i` (base `states`) of a Wolfram code is the output for the pattern whose cells
+    // read as `i` with the first neighborhood cell as the most significant digit, so rule 110
+    // means the same thing here as it does everywhere else.
+    pub fn from_wolfram_code(
+        code: &BigUint,
+        neighborhood: Neighborhood,
+        states: usize,
+    ) -> Result<Self, String> {
+        if neighborhood.cell_count() > max_cells(states) {
+            return Err(format!(
+                "neighborhood has {} cells, the limit for {} states is {}",
+                neighborhood.cell_count(),
+                states,
+                max_cells(states)
+            ));
+        }
+        let mut rules = Self::with_states(neighborhood, states);
+        rules.set_wolfram_code(code)?;
+        Ok(rules)
+    }
+
+    pub fn from_rulestring(rulestring: &str) -> Result<Self, String> {
+        Ok(Self::from_life_like(&parse_life_like(rulestring)?))
+    }
+
+    // Life-like rules always use the 3x3 Moore neighborhood.
+    pub fn from_life_like(rule: &LifeLikeRule) -> Self {
+        let mut rules = Self::with_neighborhood(Neighborhood::Moore(1));
+        for key in 0..rules.patterns() {
+            // Hash bits run down each column (dx outer, dy inner) while rulestring masks are
+            // row-major, so transpose the 3x3 square.
+            let mut mask = 0u16;
+            for cell_index in 0..9 {
+                if key & (1 << cell_index) != 0 {
+                    mask |= 1 << ((cell_index % 3) * 3 + cell_index / 3);
+                }
+            }
+            rules.set_new_cell(key, rule.output(mask));
+        }
+        rules
+    }
+
+    pub fn neighborhood(&self) -> &Neighborhood {
+        &self.neighborhood
+    }
+
+    pub fn set_neighborhood(
         &mut self,
-        neighborhood_w: usize,
-        neighborhood_h: usize,
+        neighborhood: Neighborhood,
         rng: &mut SmallRng,
+        options: &RuleOptions,
     ) {
-        self.neighborhood_w = neighborhood_w;
-        self.neighborhood_h = neighborhood_h;
-        self.randomize(rng);
+        *self = Self::with_states(neighborhood, self.states);
+        self.randomize(rng, options);
     }
 
-    pub fn randomize(&mut self, rng: &mut SmallRng) {
-        let bits = self.neighborhood_w * self.neighborhood_h;
-        assert!(
-            bits <= MAX_NEIGHBORHOOD_BITS,
-            "neighborhood too big for u64 hash"
-        );
+    pub fn randomize(&mut self, rng: &mut SmallRng, options: &RuleOptions) {
+        let classes = self.pattern_classes(options);
+        if options.lambda.is_none() && classes.is_none() && self.states == 2 {
+            // Every output is a fair coin flip, so fill whole words at once.
+            for word in self.table.iter_mut() {
+                *word = rng.gen();
+            }
+            if self.patterns < 64 {
+                self.table[0] &= (1u64 << self.patterns) - 1;
+            }
+        } else {
+            let sizes = match &classes {
+                Some((class_of, count)) => {
+                    let mut sizes = vec![0; *count];
+                    for &class in class_of {
+                        sizes[class as usize] += 1;
+                    }
+                    sizes
+                }
+                None => vec![1; self.patterns as usize],
+            };
+            let outputs = self.draw_outputs(&sizes, rng, options);
+            for key in 0..self.patterns {
+                let class = match &classes {
+                    Some((class_of, _)) => class_of[key as usize] as usize,
+                    None => key as usize,
+                };
+                self.set_new_cell(key, outputs[class]);
+            }
+        }
+        if options.quiescent {
+            self.set_new_cell(0, 0);
+        }
+    }
 
-        let total_patterns: u128 = 1u128 << bits;
+    // One output per class, where `sizes` holds each class's pattern count; class 0 is the
+    // all-zero neighborhood.
+    fn draw_outputs(&self, sizes: &[usize], rng: &mut SmallRng, options: &RuleOptions) -> Vec<u8> {
+        let count = sizes.len();
+        let states = self.states as u8;
+        match options.lambda.map(|lambda| lambda.clamp(0.0, 1.0)) {
+            Some(lambda) if options.exact_lambda => {
+                // The all-zero class is kept out of the draw when quiescent.
+                let first = options.quiescent as usize;
+                let patterns: usize = sizes.iter().sum();
+                let target = (lambda * patterns as f64).round() as usize;
+                let mut outputs = vec![0u8; count];
+                for class in pick_classes(&sizes[first..], target, rng) {
+                    outputs[first + class] = rng.gen_range(1..states);
+                }
+                outputs
+            }
+            Some(lambda) => (0..count)
+                .map(|_| {
+                    if rng.gen_bool(lambda) {
+                        rng.gen_range(1..states)
+                    } else {
+                        0
+                    }
+                })
+                .collect(),
+            None => (0..count)
+                .map(|_| rng.gen_range(0..self.states) as u8)
+                .collect(),
+        }
+    }
 
-        self.hash_map.clear();
+    // Maps every pattern to its class, numbered in order of each class's first pattern, and
+    // returns the number of classes. `None` when no symmetry applies. Symmetries that do not
+    // fit the neighborhood are left out; `check_symmetry` reports them.
+    fn pattern_classes(&self, options: &RuleOptions) -> Option<(Vec<u32>, usize)> {
+        let states = self.states as u64;
+        let digits = |mut key: u64| -> Vec<u64> {
+            (0..self.cell_count)
+                .map(|_| {
+                    let digit = key % states;
+                    key /= states;
+                    digit
+                })
+                .collect()
+        };
+        let signature: Box<dyn Fn(u64) -> u64> = match options.totalism {
+            Totalism::Totalistic => Box::new(|key| digits(key).iter().sum()),
+            Totalism::OuterTotalistic => {
+                let centre = self.centre_position()?;
+                Box::new(move |key| {
+                    let digits = digits(key);
+                    let sum: u64 = digits.iter().sum();
+                    digits[centre] * 256 + sum - digits[centre]
+                })
+            }
+            Totalism::Off => {
+                let permutations = self.symmetry_group(options);
+                if permutations.len() < 2 {
+                    return None;
+                }
+                Box::new(move |key| {
+                    let digits = digits(key);
+                    permutations
+                        .iter()
+                        .map(|permutation| {
+                            permutation
+                                .iter()
+                                .rev()
+                                .fold(0, |image, &from| image * states + digits[from])
+                        })
+                        .min()
+                        .unwrap_or(key)
+                })
+            }
+        };
 
-        for key in 0..total_patterns {
-            let out = if rng.gen_bool(0.5) { 1u8 } else { 0u8 };
-            self.hash_map.insert(key as u64, out);
+        let mut ids = HashMap::new();
+        let class_of = (0..self.patterns)
+            .map(|key| {
+                let next = ids.len() as u32;
+                *ids.entry(signature(key)).or_insert(next)
+            })
+            .collect();
+        Some((class_of, ids.len()))
+    }
+
+    pub fn check_symmetry(&self, options: &RuleOptions) -> Result<(), String> {
+        match options.totalism {
+            Totalism::OuterTotalistic if self.centre_position().is_none() => {
+                Err("outer totalistic rules need the centre cell in the neighborhood".to_string())
+            }
+            Totalism::Off => self
+                .symmetry_generators(options)
+                .into_iter()
+                .find_map(Result::err)
+                .map_or(Ok(()), Err),
+            _ => Ok(()),
         }
     }
 
+    fn centre_position(&self) -> Option<usize> {
+        self.neighborhood
+            .offsets()
+            .iter()
+            .position(|&offset| offset == (0, 0))
+    }
+
+    // Each generator lists, for every cell, the cell whose state moves there. Mirrors and
+    // turns are about the centre of the neighborhood's bounding box, so even-sized rectangles
+    // work too.
+    fn symmetry_generators(&self, options: &RuleOptions) -> Vec<Result<Vec<usize>, String>> {
+        let offsets = self.neighborhood.offsets();
+        let min_x = offsets.iter().map(|&(dx, _)| dx).min().unwrap_or(0);
+        let max_x = offsets.iter().map(|&(dx, _)| dx).max().unwrap_or(0);
+        let min_y = offsets.iter().map(|&(_, dy)| dy).min().unwrap_or(0);
+        let max_y = offsets.iter().map(|&(_, dy)| dy).max().unwrap_or(0);
+        let (sum_x, sum_y) = (min_x + max_x, min_y + max_y);
+
+        let transform = |name: &str, x: isize, y: isize| match name {
+            "left-right" => Some((sum_x - x, y)),
+            "up-down" => Some((x, sum_y - y)),
+            _ => {
+                let (twice_x, twice_y) = (sum_x + sum_y - 2 * y, 2 * x + sum_y - sum_x);
+                (twice_x % 2 == 0 && twice_y % 2 == 0).then_some((twice_x / 2, twice_y / 2))
+            }
+        };
+        let transforms = [
+            (options.mirror_x, "left-right"),
+            (options.mirror_y, "up-down"),
+            (options.rotate, "rotation"),
+        ];
+
+        transforms
+            .into_iter()
+            .filter(|&(enabled, _)| enabled)
+            .map(|(_, name)| {
+                let mut permutation = vec![0; offsets.len()];
+                for (from, &(x, y)) in offsets.iter().enumerate() {
+                    let to = transform(name, x, y)
+                        .and_then(|image| offsets.iter().position(|&offset| offset == image))
+                        .ok_or_else(|| {
+                            format!(
+                                "the {} neighborhood has no {} symmetry",
+                                self.neighborhood.label(),
+                                name
+                            )
+                        })?;
+                    permutation[to] = from;
+                }
+                Ok(permutation)
+            })
+            .collect()
+    }
+
+    // Every composition of the generators that fit, including the identity.
+    fn symmetry_group(&self, options: &RuleOptions) -> Vec<Vec<usize>> {
+        let generators: Vec<Vec<usize>> = self
+            .symmetry_generators(options)
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect();
+        let mut group = vec![(0..self.cell_count).collect::<Vec<usize>>()];
+        let mut i = 0;
+        while i < group.len() {
+            for generator in &generators {
+                let composed: Vec<usize> = generator.iter().map(|&from| group[i][from]).collect();
+                if !group.contains(&composed) {
+                    group.push(composed);
+                }
+            }
+            i += 1;
+        }
+        group
+    }
+
+    // Fraction of outputs that are not the quiescent state 0.
+    pub fn lambda(&self) -> f64 {
+        let active = (0..self.patterns)
+            .filter(|&key| self.get_new_cell(key) != 0)
+            .count();
+        active as f64 / self.patterns as f64
+    }
+
+    // Each output is replaced with a different state with probability `rate`.
+    pub fn mutate(&mut self, rate: f64, rng: &mut SmallRng) {
+        let rate = rate.clamp(0.0, 1.0);
+        for key in 0..self.patterns {
+            if rng.gen_bool(rate) {
+                let output = self.get_new_cell(key);
+                let shift = rng.gen_range(1..self.states) as u8;
+                self.set_new_cell(key, (output + shift) % self.states as u8);
+            }
+        }
+    }
+
+    // Uniform crossover: every output comes from either parent with equal chance.
+    pub fn crossover(&self, other: &Self, rng: &mut SmallRng) -> Result<Self, String> {
+        if self.neighborhood != other.neighborhood || self.states != other.states {
+            return Err(
+                "crossover needs two rules with the same neighborhood and states".to_string(),
+            );
+        }
+        let mut child = self.clone();
+        for key in 0..self.patterns {
+            if rng.gen_bool(0.5) {
+                child.set_new_cell(key, other.get_new_cell(key));
+            }
+        }
+        Ok(child)
+    }
+
+    pub fn set_wolfram_code(&mut self, code: &BigUint) -> Result<(), String> {
+        let digits = code.to_radix_le(self.states as u32);
+        if digits.len() as u64 > self.patterns {
+            return Err(format!(
+                "rule number has {} base-{} digits but a {}-cell neighborhood only has {} patterns",
+                digits.len(),
+                self.states,
+                self.cell_count,
+                self.patterns
+            ));
+        }
+
+        for key in 0..self.patterns {
+            let digit = digits
+                .get(self.wolfram_index(key) as usize)
+                .copied()
+                .unwrap_or(0);
+            self.set_new_cell(key, digit);
+        }
+        Ok(())
+    }
+
+    pub fn wolfram_code(&self) -> BigUint {
+        let mut digits = vec![0u8; self.patterns as usize];
+        for key in 0..self.patterns {
+            digits[self.wolfram_index(key) as usize] = self.get_new_cell(key);
+        }
+        BigUint::from_radix_le(&digits, self.states as u32).unwrap_or_default()
+    }
+
     pub fn get_new_cell(&self, neighborhood: u64) -> u8 {
-        self.hash_map[&neighborhood]
+        let bit = neighborhood * self.entry_bits;
+        let mask = (1u64 << self.entry_bits) - 1;
+        ((self.table[(bit >> 6) as usize] >> (bit & 63)) & mask) as u8
+    }
+
+    pub fn set_new_cell(&mut self, neighborhood: u64, out: u8) {
+        let bit = neighborhood * self.entry_bits;
+        let mask = ((1u64 << self.entry_bits) - 1) << (bit & 63);
+        let word = &mut self.table[(bit >> 6) as usize];
+        *word = (*word & !mask) | ((out as u64) << (bit & 63) & mask);
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.cell_count
+    }
+
+    pub fn states(&self) -> usize {
+        self.states
+    }
+
+    pub fn patterns(&self) -> u64 {
+        self.patterns
+    }
+
+    // Neighborhood hashes put the first cell in the lowest digit, Wolfram codes put it in the
+    // highest.
+    fn wolfram_index(&self, mut key: u64) -> u64 {
+        let states = self.states as u64;
+        let mut index = 0u64;
+        for _ in 0..self.cell_count {
+            index = index * states + key % states;
+            key /= states;
+        }
+        index
+    }
+}
+
+// Picks classes at random whose sizes add up to `target`, or as close to it as the sizes
+// allow. Classes of the same size are interchangeable, so this is a bounded knapsack over the
+// distinct sizes, each usable at most as many times as there are classes of it: the sums
+// reachable after each size are built up in turn, the closest reachable sum is taken, and
+// walking back through the sizes draws a feasible count of each before sampling that many of
+// its classes.
+fn pick_classes(sizes: &[usize], target: usize, rng: &mut SmallRng) -> Vec<usize> {
+    let mut by_size: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (class, &size) in sizes.iter().enumerate() {
+        by_size.entry(size).or_default().push(class);
+    }
+    let total: usize = sizes.iter().sum();
+
+    // `reachable[i][sum]`: some classes of the first `i` sizes add up to `sum`.
+    let mut reachable = vec![vec![false; total + 1]];
+    reachable[0][0] = true;
+    for (&size, classes) in &by_size {
+        let previous = &reachable[reachable.len() - 1];
+        let mut next = vec![false; total + 1];
+        // Fewest classes of this size needed on top of `previous` to reach each sum.
+        let mut used = vec![0; total + 1];
+        for sum in 0..=total {
+            if previous[sum] {
+                next[sum] = true;
+            } else if sum >= size && next[sum - size] && used[sum - size] < classes.len() {
+                next[sum] = true;
+                used[sum] = used[sum - size] + 1;
+            }
+        }
+        reachable.push(next);
+    }
+
+    let mut sum = (0..=total)
+        .filter(|&sum| reachable[by_size.len()][sum])
+        .min_by_key(|&sum| sum.abs_diff(target))
+        .unwrap_or(0);
+    let mut picked = Vec::new();
+    for (stage, (&size, classes)) in by_size.iter().enumerate().rev() {
+        let counts: Vec<usize> = (0..=classes.len().min(sum / size))
+            .filter(|&count| reachable[stage][sum - count * size])
+            .collect();
+        let count = match counts.len() {
+            1 => counts[0],
+            _ => counts[rng.gen_range(0..counts.len())],
+        };
+        sum -= count * size;
+        picked.extend(
+            index::sample(rng, classes.len(), count)
+                .into_iter()
+                .map(|index| classes[index]),
+        );
+    }
+    picked
+}
+
+// Largest neighborhood whose patterns still fit in the table for this many states.
+pub fn max_cells(states: usize) -> usize {
+    let mut cells = 0u32;
+    while (states as u64).pow(cells + 1) <= MAX_PATTERNS {
+        cells += 1;
+    }
+    cells as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::rand::SeedableRng;
+
+    fn rectangle(width: usize, height: usize) -> Neighborhood {
+        Neighborhood::Rectangle { width, height }
+    }
+
+    fn randomized(
+        neighborhood: Neighborhood,
+        states: usize,
+        options: &RuleOptions,
+    ) -> RulesCollection {
+        let mut rules = RulesCollection::with_states(neighborhood, states);
+        rules.randomize(&mut SmallRng::seed_from_u64(17), options);
+        rules
+    }
+
+    fn active(rules: &RulesCollection) -> usize {
+        (0..rules.patterns())
+            .filter(|&key| rules.get_new_cell(key) != 0)
+            .count()
+    }
+
+    #[test]
+    fn exact_lambda_sets_that_fraction_of_outputs() {
+        for (neighborhood, states) in [
+            (rectangle(3, 1), 2),
+            (Neighborhood::Moore(1), 2),
+            (rectangle(2, 2), 5),
+        ] {
+            for lambda in [0.0, 0.1, 0.37, 0.5, 1.0] {
+                for quiescent in [false, true] {
+                    let options = RuleOptions {
+                        lambda: Some(lambda),
+                        exact_lambda: true,
+                        quiescent,
+                        ..RuleOptions::default()
+                    };
+                    let rules = randomized(neighborhood.clone(), states, &options);
+                    let patterns = rules.patterns() as usize;
+                    let target = ((lambda * patterns as f64).round() as usize)
+                        .min(patterns - quiescent as usize);
+                    assert_eq!(
+                        active(&rules),
+                        target,
+                        "{} {} {}",
+                        neighborhood.label(),
+                        lambda,
+                        quiescent
+                    );
+                    if quiescent {
+                        assert_eq!(rules.get_new_cell(0), 0);
+                    }
+                }
+            }
+        }
+    }
+
+    fn digits(rules: &RulesCollection, mut key: u64) -> Vec<u64> {
+        let states = rules.states() as u64;
+        (0..rules.cell_count())
+            .map(|_| {
+                let digit = key % states;
+                key /= states;
+                digit
+            })
+            .collect()
+    }
+
+    // Checks that moving every cell of every pattern to `transform` of its offset, worked in
+    // doubled coordinates about the centre of the bounding box, keeps the output.
+    fn assert_invariant(rules: &RulesCollection, transform: fn((isize, isize)) -> (isize, isize)) {
+        let offsets = rules.neighborhood().offsets();
+        let span = |axis: fn(&(isize, isize)) -> isize| {
+            offsets.iter().map(axis).min().unwrap() + offsets.iter().map(axis).max().unwrap()
+        };
+        let (span_x, span_y) = (span(|o| o.0), span(|o| o.1));
+        let image: Vec<usize> = offsets
+            .iter()
+            .map(|&(dx, dy)| {
+                let (x, y) = transform((2 * dx - span_x, 2 * dy - span_y));
+                let moved = ((x + span_x) / 2, (y + span_y) / 2);
+                offsets.iter().position(|&offset| offset == moved).unwrap()
+            })
+            .collect();
+        let states = rules.states() as u64;
+        for key in 0..rules.patterns() {
+            let digits = digits(rules, key);
+            let mut moved = vec![0; digits.len()];
+            for (from, &to) in image.iter().enumerate() {
+                moved[to] = digits[from];
+            }
+            let moved_key = moved
+                .iter()
+                .rev()
+                .fold(0, |key, &digit| key * states + digit);
+            assert_eq!(
+                rules.get_new_cell(key),
+                rules.get_new_cell(moved_key),
+                "{} patterns {} and {}",
+                rules.neighborhood().label(),
+                key,
+                moved_key
+            );
+        }
+    }
+
+    #[test]
+    fn symmetric_tables_are_invariant() {
+        let mirror_x = RuleOptions {
+            mirror_x: true,
+            ..RuleOptions::default()
+        };
+        let mirror_y = RuleOptions {
+            mirror_y: true,
+            ..RuleOptions::default()
+        };
+        let rotate = RuleOptions {
+            rotate: true,
+            lambda: Some(0.4),
+            ..RuleOptions::default()
+        };
+        let all = RuleOptions {
+            mirror_x: true,
+            mirror_y: true,
+            rotate: true,
+            ..RuleOptions::default()
+        };
+        let flip_x: fn((isize, isize)) -> (isize, isize) = |(x, y)| (-x, y);
+        let flip_y: fn((isize, isize)) -> (isize, isize) = |(x, y)| (x, -y);
+        let turn: fn((isize, isize)) -> (isize, isize) = |(x, y)| (-y, x);
+
+        for (neighborhood, states) in [
+            (Neighborhood::Moore(1), 2),
+            (rectangle(4, 2), 3),
+            (rectangle(5, 1), 2),
+        ] {
+            let rules = randomized(neighborhood.clone(), states, &mirror_x);
+            assert_invariant(&rules, flip_x);
+            assert!(rules.check_symmetry(&mirror_x).is_ok());
+        }
+        for (neighborhood, states) in [(Neighborhood::Moore(1), 2), (rectangle(2, 4), 3)] {
+            assert_invariant(&randomized(neighborhood, states, &mirror_y), flip_y);
+        }
+        for (neighborhood, states) in [
+            (Neighborhood::Moore(1), 2),
+            (rectangle(2, 2), 4),
+            (Neighborhood::VonNeumann(2), 2),
+        ] {
+            let rules = randomized(neighborhood.clone(), states, &rotate);
+            assert_invariant(&rules, turn);
+            let rules = randomized(neighborhood, states, &all);
+            for transform in [flip_x, flip_y, turn] {
+                assert_invariant(&rules, transform);
+            }
+        }
+
+        assert!(RulesCollection::with_states(rectangle(3, 1), 2)
+            .check_symmetry(&rotate)
+            .is_err());
+        let lopsided = Neighborhood::custom([(0, 0), (1, 0), (1, 1)]).unwrap();
+        assert!(RulesCollection::with_states(lopsided, 2)
+            .check_symmetry(&mirror_x)
+            .is_err());
+    }
+
+    #[test]
+    fn totalistic_tables_depend_only_on_sums() {
+        for (totalism, neighborhood, states) in [
+            (Totalism::Totalistic, Neighborhood::Moore(1), 2),
+            (Totalism::Totalistic, rectangle(2, 2), 4),
+            (Totalism::OuterTotalistic, Neighborhood::Moore(1), 2),
+            (Totalism::OuterTotalistic, Neighborhood::VonNeumann(1), 3),
+        ] {
+            let options = RuleOptions {
+                totalism,
+                ..RuleOptions::default()
+            };
+            let rules = randomized(neighborhood.clone(), states, &options);
+            let centre = rules
+                .neighborhood()
+                .offsets()
+                .iter()
+                .position(|&offset| offset == (0, 0));
+            let mut outputs = HashMap::new();
+            for key in 0..rules.patterns() {
+                let digits = digits(&rules, key);
+                let sum: u64 = digits.iter().sum();
+                let signature = match totalism {
+                    Totalism::OuterTotalistic => {
+                        let centre = digits[centre.unwrap()];
+                        (Some(centre), sum - centre)
+                    }
+                    _ => (None, sum),
+                };
+                let output = *outputs.entry(signature).or_insert(rules.get_new_cell(key));
+                assert_eq!(
+                    rules.get_new_cell(key),
+                    output,
+                    "{} pattern {}",
+                    neighborhood.label(),
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn exact_lambda_counts_patterns_of_symmetric_tables() {
+        for (options, neighborhood) in [
+            (
+                RuleOptions {
+                    mirror_x: true,
+                    ..RuleOptions::default()
+                },
+                Neighborhood::Moore(1),
+            ),
+            (
+                RuleOptions {
+                    rotate: true,
+                    quiescent: true,
+                    ..RuleOptions::default()
+                },
+                Neighborhood::Moore(1),
+            ),
+            (
+                RuleOptions {
+                    totalism: Totalism::Totalistic,
+                    ..RuleOptions::default()
+                },
+                Neighborhood::Moore(1),
+            ),
+            (
+                RuleOptions {
+                    totalism: Totalism::OuterTotalistic,
+                    ..RuleOptions::default()
+                },
+                rectangle(3, 1),
+            ),
+        ] {
+            for lambda in [0.1, 0.25, 0.5, 0.9] {
+                let options = RuleOptions {
+                    lambda: Some(lambda),
+                    exact_lambda: true,
+                    ..options.clone()
+                };
+                let rules = randomized(neighborhood.clone(), 2, &options);
+                let (class_of, count) = rules.pattern_classes(&options).unwrap();
+                let mut sizes = vec![0; count];
+                for &class in &class_of {
+                    sizes[class as usize] += 1;
+                }
+                // Every pattern count the classes can add up to, keeping out the all-zero
+                // class when quiescent.
+                let patterns = rules.patterns() as usize;
+                let mut reachable = vec![false; patterns + 1];
+                reachable[0] = true;
+                for &size in &sizes[options.quiescent as usize..] {
+                    for sum in (size..=patterns).rev() {
+                        reachable[sum] |= reachable[sum - size];
+                    }
+                }
+                let target = (lambda * patterns as f64).round() as usize;
+                let closest = (0..=patterns)
+                    .filter(|&sum| reachable[sum])
+                    .map(|sum| sum.abs_diff(target))
+                    .min()
+                    .unwrap();
+                assert_eq!(active(&rules).abs_diff(target), closest, "{:?}", options);
+            }
+        }
     }
 }
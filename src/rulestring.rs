@@ -0,0 +1,432 @@
+// Masks here use a row-major 3x3 layout: bit 0 is the north-west cell, bit 4 the centre and
+// bit 8 the south-east cell.
+const CENTER: u16 = 1 << 4;
+const NEIGHBORS: u16 = 0b1_1110_1111;
+const PATTERNS: usize = 1 << 9;
+
+// Hensel's isotropic letters for each neighbor count with one representative per letter.
+// Counts above 4 use the letters of the complementary count.
+const HENSEL_LETTERS: [&str; 5] = ["", "ce", "ceaikn", "ceaiknjqry", "ceaiknjqrtwyz"];
+const HENSEL_MASKS: [&[u16]; 5] = [
+    &[],
+    &[1, 2],
+    &[5, 10, 3, 40, 33, 68],
+    &[69, 42, 11, 7, 98, 13, 14, 70, 41, 97],
+    &[325, 170, 15, 45, 99, 71, 78, 102, 43, 105, 106, 101, 108],
+];
+
+const MAX_GENERATIONS_STATES: usize = 256;
+
+#[derive(Clone)]
+pub struct LifeLikeRule {
+    birth: Vec<bool>,
+    survival: Vec<bool>,
+}
+
+impl LifeLikeRule {
+    pub fn output(&self, mask: u16) -> u8 {
+        let alive = if mask & CENTER != 0 {
+            self.survives(mask)
+        } else {
+            self.born(mask)
+        };
+        alive as u8
+    }
+
+    fn born(&self, mask: u16) -> bool {
+        self.birth[(mask & NEIGHBORS) as usize]
+    }
+
+    fn survives(&self, mask: u16) -> bool {
+        self.survival[(mask & NEIGHBORS) as usize]
+    }
+}
+
+// Golly's "Generations" family: state 1 is alive, and a live cell that fails to survive
+// steps through the refractory states 2..states before it is dead again. Only state 1 cells
+// count as neighbors.
+#[derive(Clone)]
+pub struct GenerationsRule {
+    life: LifeLikeRule,
+    states: usize,
+}
+
+impl GenerationsRule {
+    pub fn states(&self) -> usize {
+        self.states
+    }
+
+    // `mask` holds the live (state 1) Moore neighbors; its centre bit is ignored.
+    pub fn next_state(&self, state: u8, mask: u16) -> u8 {
+        match state {
+            0 => self.life.born(mask) as u8,
+            1 if self.life.survives(mask) => 1,
+            _ => ((state as usize + 1) % self.states) as u8,
+        }
+    }
+
+    pub fn as_life_like(&self) -> Option<&LifeLikeRule> {
+        (self.states == 2).then_some(&self.life)
+    }
+}
+
+// Accepts `B3/S23`, `b3s23`, the older survival-first `23/3` form and Hensel letters such as
+// `B2-a/S12`.
+pub fn parse_life_like(rulestring: &str) -> Result<LifeLikeRule, String> {
+    let rule = parse_generations(rulestring)?;
+    if rule.states != 2 {
+        return Err(format!(
+            "rule has {} states, Life-like rules have 2",
+            rule.states
+        ));
+    }
+    Ok(rule.life)
+}
+
+// Accepts everything `parse_life_like` does plus a state count, as in `B2/S/C3`, `B2/S345/4`
+// or Golly's `345/2/4`. Without one the rule has 2 states.
+pub fn parse_generations(rulestring: &str) -> Result<GenerationsRule, String> {
+    let s: String = rulestring
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if s.is_empty() {
+        return Err("rulestring is empty".to_string());
+    }
+    let parts: Vec<&str> = s.split('/').filter(|part| !part.is_empty()).collect();
+
+    if !s.starts_with('b') && !s.starts_with('s') {
+        // Survival first; an empty survival set still leaves a leading '/'.
+        let parts: Vec<&str> = s.split('/').collect();
+        let (survival, birth, states) = match parts.as_slice() {
+            [survival, birth] => (survival, birth, None),
+            [survival, birth, states] => (survival, birth, Some(parse_states(states)?)),
+            _ => return Err("expected B.../S... or survival/birth notation".to_string()),
+        };
+        return Ok(GenerationsRule {
+            life: LifeLikeRule {
+                birth: parse_counts(birth)?,
+                survival: parse_counts(survival)?,
+            },
+            states: states.unwrap_or(2),
+        });
+    }
+
+    let mut birth = None;
+    let mut survival = None;
+    let mut states = None;
+    for part in parts {
+        let states_part = part
+            .strip_prefix('c')
+            .or_else(|| part.chars().all(|c| c.is_ascii_digit()).then_some(part));
+        if let Some(count) = states_part.filter(|_| birth.is_some() && survival.is_some()) {
+            if states.is_some() {
+                return Err("state count given twice".to_string());
+            }
+            states = Some(parse_states(count)?);
+            continue;
+        }
+
+        let mut section: Option<char> = None;
+        let mut spec = String::new();
+        for c in part.chars().chain(std::iter::once('/')) {
+            if c == 'b' || c == 's' || c == '/' {
+                if let Some(name) = section.take() {
+                    let slot = if name == 'b' {
+                        &mut birth
+                    } else {
+                        &mut survival
+                    };
+                    if slot.is_some() {
+                        return Err(format!(
+                            "'{}' section given twice",
+                            name.to_ascii_uppercase()
+                        ));
+                    }
+                    *slot = Some(parse_counts(&spec)?);
+                    spec.clear();
+                } else if !spec.is_empty() {
+                    return Err(format!("'{}' is not inside a B or S section", spec));
+                }
+                if c != '/' {
+                    section = Some(c);
+                }
+            } else {
+                spec.push(c);
+            }
+        }
+    }
+
+    Ok(GenerationsRule {
+        life: LifeLikeRule {
+            birth: birth.ok_or("missing B section")?,
+            survival: survival.ok_or("missing S section")?,
+        },
+        states: states.unwrap_or(2),
+    })
+}
+
+fn parse_states(count: &str) -> Result<usize, String> {
+    count
+        .parse::<usize>()
+        .ok()
+        .filter(|states| (2..=MAX_GENERATIONS_STATES).contains(states))
+        .ok_or_else(|| {
+            format!(
+                "state count '{}' must be between 2 and {}",
+                count, MAX_GENERATIONS_STATES
+            )
+        })
+}
+
+fn parse_counts(spec: &str) -> Result<Vec<bool>, String> {
+    let mut set = vec![false; PATTERNS];
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let count = chars[i]
+            .to_digit(10)
+            .filter(|d| *d <= 8)
+            .ok_or_else(|| format!("expected a neighbor count 0-8, found '{}'", chars[i]))?
+            as usize;
+        i += 1;
+        let negate = chars.get(i) == Some(&'-');
+        if negate {
+            i += 1;
+        }
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_alphabetic() {
+            i += 1;
+        }
+        if negate && start == i {
+            return Err(format!("'{}-' must be followed by letters", count));
+        }
+        let classes = chars[start..i]
+            .iter()
+            .map(|&letter| {
+                hensel_class(count, letter)
+                    .ok_or_else(|| format!("'{}{}' is not a valid Hensel class", count, letter))
+            })
+            .collect::<Result<Vec<u16>, String>>()?;
+
+        for mask in 0..PATTERNS as u16 {
+            if mask & CENTER != 0 || mask.count_ones() as usize != count {
+                continue;
+            }
+            let listed = classes.contains(&canonical(mask));
+            let include = if negate {
+                !listed
+            } else {
+                classes.is_empty() || listed
+            };
+            if include {
+                set[mask as usize] = true;
+            }
+        }
+    }
+    Ok(set)
+}
+
+fn hensel_class(count: usize, letter: char) -> Option<u16> {
+    let (base, complement) = if count > 4 {
+        (8 - count, true)
+    } else {
+        (count, false)
+    };
+    let index = HENSEL_LETTERS[base].find(letter)?;
+    let mask = HENSEL_MASKS[base][index];
+    Some(canonical(if complement { NEIGHBORS & !mask } else { mask }))
+}
+
+// Smallest mask among the eight rotations and reflections of the 3x3 square.
+fn canonical(mask: u16) -> u16 {
+    (0..8)
+        .map(|symmetry| transform(mask, symmetry))
+        .min()
+        .unwrap_or(mask)
+}
+
+fn transform(mask: u16, symmetry: usize) -> u16 {
+    let mut out = 0u16;
+    for idx in 0..9 {
+        if mask & (1 << idx) == 0 {
+            continue;
+        }
+        let (mut x, mut y) = (idx % 3, idx / 3);
+        if symmetry & 1 != 0 {
+            x = 2 - x;
+        }
+        if symmetry & 2 != 0 {
+            y = 2 - y;
+        }
+        if symmetry & 4 != 0 {
+            std::mem::swap(&mut x, &mut y);
+        }
+        out |= 1 << (y * 3 + x);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads a 3x3 block such as "##./.#./..." row by row into a mask.
+    fn mask(block: &str) -> u16 {
+        block
+            .chars()
+            .filter(|&c| c != '/')
+            .enumerate()
+            .filter(|&(_, c)| c == '#')
+            .fold(0, |mask, (bit, _)| mask | 1 << bit)
+    }
+
+    #[test]
+    fn life() {
+        for rulestring in ["B3/S23", "b3s23", "23/3", "B3 / S23"] {
+            let rule = parse_life_like(rulestring).unwrap();
+            assert_eq!(rule.output(mask("#.#/.../#..")), 1, "{}", rulestring);
+            assert_eq!(rule.output(mask("#.#/.../...")), 0, "{}", rulestring);
+            assert_eq!(rule.output(mask("#.#/.#./...")), 1, "{}", rulestring);
+            assert_eq!(rule.output(mask("#.#/.#./#..")), 1, "{}", rulestring);
+            assert_eq!(rule.output(mask("#.#/.#./#.#")), 0, "{}", rulestring);
+            assert_eq!(rule.output(mask(".../.#./...")), 0, "{}", rulestring);
+        }
+    }
+
+    #[test]
+    fn highlife() {
+        let rule = parse_life_like("B36/S23").unwrap();
+        assert_eq!(rule.output(mask("###/.../###")), 1);
+        assert_eq!(rule.output(mask("###/.#./###")), 0);
+        assert_eq!(rule.output(mask("##./.../#..")), 1);
+        assert_eq!(rule.output(mask("##./.../##.")), 0);
+    }
+
+    #[test]
+    fn hensel_letters_cover_every_rotation_and_reflection() {
+        let rule = parse_life_like("B2-a/S12").unwrap();
+        // 2a: two adjacent cells, one of them a corner.
+        for adjacent in ["##./.../...", ".##/.../...", "..#/..#/...", "#../#../..."] {
+            assert_eq!(rule.output(mask(adjacent)), 0, "{}", adjacent);
+        }
+        // 2c, 2e, 2i, 2k and 2n are still born.
+        for other in [
+            "#.#/.../...",
+            ".#./#../...",
+            ".../#.#/...",
+            "#../..#/...",
+            "..#/.../#..",
+        ] {
+            assert_eq!(rule.output(mask(other)), 1, "{}", other);
+        }
+        assert_eq!(rule.output(mask("#../.#./...")), 1);
+        assert_eq!(rule.output(mask("##./.#./...")), 1);
+        assert_eq!(rule.output(mask("##./.##/...")), 0);
+
+        let rule = parse_life_like("B3/S2c").unwrap();
+        assert_eq!(rule.output(mask("#.#/.#./...")), 1);
+        assert_eq!(rule.output(mask("#../.#./..#")), 0);
+    }
+
+    #[test]
+    fn every_four_neighbor_letter_matches_its_shape() {
+        let shapes = [
+            ('c', "#.#/.../#.#"),
+            ('e', ".#./#.#/.#."),
+            ('a', ".##/..#/..#"),
+            ('i', ".##/.../.##"),
+            ('k', "#.#/..#/.#."),
+            ('n', "#.#/..#/..#"),
+            ('j', "##./..#/..#"),
+            ('q', "#../..#/.##"),
+            ('r', ".##/..#/.#."),
+            ('t', "###/.../.#."),
+            ('w', "##./..#/.#."),
+            ('y', "#.#/.../.##"),
+            ('z', "##./.../.##"),
+        ];
+        // Each shape in its four quarter turns and their mirror images.
+        let turn = |mask: u16| {
+            (0..9)
+                .filter(|bit| mask >> bit & 1 != 0)
+                .fold(0u16, |turned, bit| {
+                    turned | 1 << ((2 - bit / 3) + 3 * (bit % 3))
+                })
+        };
+        let flip = |mask: u16| {
+            (0..9)
+                .filter(|bit| mask >> bit & 1 != 0)
+                .fold(0u16, |flipped, bit| {
+                    flipped | 1 << ((2 - bit % 3) + 3 * (bit / 3))
+                })
+        };
+        for (letter, shape) in shapes {
+            let mut orientations = vec![mask(shape)];
+            for _ in 0..3 {
+                orientations.push(turn(*orientations.last().unwrap()));
+            }
+            orientations.extend(orientations.clone().into_iter().map(flip));
+
+            let rule = parse_life_like(&format!("B4{}/S", letter)).unwrap();
+            for neighbors in (0..PATTERNS as u16).filter(|&mask| mask & CENTER == 0) {
+                if neighbors.count_ones() != 4 {
+                    continue;
+                }
+                let expected = orientations.contains(&neighbors) as u8;
+                assert_eq!(
+                    rule.output(neighbors),
+                    expected,
+                    "4{} on {:09b}",
+                    letter,
+                    neighbors
+                );
+            }
+            let rule = parse_life_like(&format!("B4-{}/S", letter)).unwrap();
+            assert_eq!(rule.output(mask(shape)), 0, "4-{}", letter);
+        }
+    }
+
+    #[test]
+    fn generations() {
+        for rulestring in ["B2/S/C3", "/2/3", "b2s/3"] {
+            let rule = parse_generations(rulestring).unwrap();
+            assert_eq!(rule.states(), 3, "{}", rulestring);
+            assert!(rule.as_life_like().is_none());
+            assert_eq!(rule.next_state(0, mask("#.#/.../...")), 1, "{}", rulestring);
+            assert_eq!(rule.next_state(0, mask("#.#/.../..#")), 0, "{}", rulestring);
+            assert_eq!(rule.next_state(1, mask("#.#/.../...")), 2, "{}", rulestring);
+            assert_eq!(rule.next_state(2, mask("#.#/.../...")), 0, "{}", rulestring);
+        }
+
+        let rule = parse_generations("345/2/4").unwrap();
+        assert_eq!(rule.states(), 4);
+        assert_eq!(rule.next_state(1, mask("###/.../...")), 1);
+        assert_eq!(rule.next_state(1, mask("##./.../...")), 2);
+        assert_eq!(rule.next_state(3, 0), 0);
+
+        assert!(parse_generations("B3/S23")
+            .unwrap()
+            .as_life_like()
+            .is_some());
+    }
+
+    #[test]
+    fn errors() {
+        for rulestring in [
+            "",
+            "B9/S23",
+            "B3/S23/C1",
+            "B3/S23/C257",
+            "B3/S23/C3/C4",
+            "B3/B2/S23",
+            "B3",
+            "3/23/2/1",
+            "B2x/S",
+        ] {
+            assert!(parse_generations(rulestring).is_err(), "{}", rulestring);
+        }
+        assert!(parse_life_like("B2/S/C3").is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::automata::{Automata, Boundary};
+use crate::config::MAX_STATES;
+use crate::neighborhood::Neighborhood;
+use crate::rules::{RuleOptions, RulesCollection};
+use crate::rulestring::{parse_generations, GenerationsRule};
+use crate::settings::check_texture_size;
+
+// Bump whenever the fields below change meaning; older files are rejected rather than
+// misread.
+pub const SESSION_VERSION: u32 = 2;
+
+// Everything needed to put a board back exactly as it was, with the history above it and the
+// options new tables are drawn with. The rule table is stored as its Wolfram code and each
+// board row as hex digits, two per cell when a Generations rule has more than 16 states.
+// Text boxes are refilled from these values; file paths, the mutation rate and the view are
+// not saved.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub grid_w: usize,
+    pub grid_h: usize,
+    pub history_length: usize,
+    pub step_time: f32,
+    pub spawn_chance: f32,
+    pub states: usize,
+    pub neighborhood: Neighborhood,
+    pub rule: String,
+    pub generations: Option<String>,
+    pub rule_options: RuleOptions,
+    pub boundary_x: Boundary,
+    pub boundary_y: Boundary,
+    pub rule_seed: u64,
+    pub board_seed: u64,
+    pub cells: Vec<String>,
+    // The boards before `cells`, oldest first, in the same encoding.
+    pub history: Vec<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+impl Session {
+    // `generations` is the rulestring of the running Generations rule, if there is one, and
+    // `history` the boards before the current one, oldest first.
+    pub fn capture(
+        automata: &Automata,
+        generations: Option<String>,
+        history: &[Vec<u8>],
+        history_length: usize,
+        step_time: f32,
+    ) -> Self {
+        let (grid_w, grid_h) = automata.size();
+        let (boundary_x, boundary_y) = automata.boundary();
+        let digits = if automata.states() > 16 { 2 } else { 1 };
+        let encode = |cells: &[u8]| -> Vec<String> {
+            cells
+                .chunks(grid_w)
+                .map(|row| {
+                    row.iter()
+                        .map(|state| format!("{:01$x}", state, digits))
+                        .collect()
+                })
+                .collect()
+        };
+        Self {
+            version: SESSION_VERSION,
+            grid_w,
+            grid_h,
+            history_length,
+            step_time,
+            spawn_chance: automata.spawn_chance(),
+            states: automata.rules().states(),
+            neighborhood: automata.neighborhood().clone(),
+            rule: automata.rules().wolfram_code().to_string(),
+            generations: automata.generations().and(generations),
+            rule_options: automata.rule_options().clone(),
+            boundary_x,
+            boundary_y,
+            rule_seed: automata.rule_seed(),
+            board_seed: automata.board_seed(),
+            cells: encode(&automata.cells()),
+            history: history.iter().map(|cells| encode(cells)).collect(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        fs::write(path, json).map_err(|err| format!("could not write {}: {}", path, err))
+    }
+
+    // Also checks that the rule and the board fit together, so a loaded session can be
+    // applied without further validation.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json =
+            fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+        let header: Header = serde_json::from_str(&json)
+            .map_err(|err| format!("{} is not a session file: {}", path, err))?;
+        if header.version != SESSION_VERSION {
+            return Err(format!(
+                "session version {} is not supported (expected {})",
+                header.version, SESSION_VERSION
+            ));
+        }
+        let mut session: Session = serde_json::from_str(&json)
+            .map_err(|err| format!("{} is not a session file: {}", path, err))?;
+
+        if session.grid_w == 0 || session.grid_h == 0 || session.history_length == 0 {
+            return Err("board size and history length must be at least 1".to_string());
+        }
+        check_texture_size(session.grid_w, session.grid_h, session.history_length)?;
+        if !(2..=MAX_STATES).contains(&session.states) {
+            return Err(format!("states must be between 2 and {}", MAX_STATES));
+        }
+        if let Neighborhood::Custom(offsets) = &session.neighborhood {
+            session.neighborhood = Neighborhood::custom(offsets.iter().copied())?;
+        }
+        if session.neighborhood.cell_count() == 0 {
+            return Err("neighborhood needs at least one cell".to_string());
+        }
+        if let Some(lambda) = session.rule_options.lambda {
+            if !(0.0..=1.0).contains(&lambda) {
+                return Err("lambda must be between 0 and 1".to_string());
+            }
+        }
+        if session.history.len() >= session.history_length {
+            return Err(format!(
+                "expected at most {} history boards, found {}",
+                session.history_length - 1,
+                session.history.len()
+            ));
+        }
+        session.rules()?;
+        session.generations_rule()?;
+        session.board()?;
+        session.history()?;
+        Ok(session)
+    }
+
+    pub fn rules(&self) -> Result<RulesCollection, String> {
+        let code = self
+            .rule
+            .parse::<BigUint>()
+            .map_err(|_| "rule must be a non-negative integer".to_string())?;
+        RulesCollection::from_wolfram_code(&code, self.neighborhood.clone(), self.states)
+    }
+
+    pub fn generations_rule(&self) -> Result<Option<GenerationsRule>, String> {
+        self.generations
+            .as_deref()
+            .map(parse_generations)
+            .transpose()
+    }
+
+    pub fn board(&self) -> Result<Vec<u8>, String> {
+        self.decode(&self.cells, "board")
+    }
+
+    pub fn history(&self) -> Result<Vec<Vec<u8>>, String> {
+        self.history
+            .iter()
+            .enumerate()
+            .map(|(i, rows)| self.decode(rows, &format!("history board {}", i + 1)))
+            .collect()
+    }
+
+    // `name` says which board errors are about.
+    fn decode(&self, rows: &[String], name: &str) -> Result<Vec<u8>, String> {
+        if rows.len() != self.grid_h {
+            return Err(format!(
+                "expected {} {} rows, found {}",
+                self.grid_h,
+                name,
+                rows.len()
+            ));
+        }
+        let states = match self.generations_rule()? {
+            Some(rule) => rule.states(),
+            None => self.states,
+        };
+        let mut board = Vec::with_capacity(self.grid_w * self.grid_h);
+        for (y, row) in rows.iter().enumerate() {
+            let digits = row.len() / self.grid_w;
+            if !(1..=2).contains(&digits) || row.len() != digits * self.grid_w {
+                return Err(format!("{} row {} has the wrong length", name, y + 1));
+            }
+            for x in 0..self.grid_w {
+                let state = row
+                    .get(x * digits..(x + 1) * digits)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .filter(|&state| (state as usize) < states)
+                    .ok_or_else(|| {
+                        format!(
+                            "{} row {} column {} is not a valid state",
+                            name,
+                            y + 1,
+                            x + 1
+                        )
+                    })?;
+                board.push(state);
+            }
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Totalism;
+    use crate::rulestring::parse_generations;
+
+    // Saves to a file of its own under the temp directory and loads it back.
+    fn save_and_load(session: &Session, name: &str) -> Result<Session, String> {
+        let path = std::env::temp_dir().join(format!(
+            "cellular-automata-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        let path = path.to_string_lossy();
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path);
+        let _ = fs::remove_file(&*path);
+        loaded
+    }
+
+    fn automata() -> Automata {
+        let mut automata = Automata::with_seeds(12, 5, Neighborhood::Moore(1), 3, 0.4, 7, 8);
+        automata.set_boundary(Boundary::ALL[1], Boundary::ALL[3]);
+        automata.set_rule_options(RuleOptions {
+            lambda: Some(0.3),
+            exact_lambda: true,
+            quiescent: true,
+            mirror_x: true,
+            totalism: Totalism::OuterTotalistic,
+            ..RuleOptions::default()
+        });
+        automata.reseed_rules(7);
+        automata
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        let mut automata = automata();
+        let mut history = Vec::new();
+        for _ in 0..3 {
+            history.push(automata.cells());
+            automata.step();
+        }
+        let session = Session::capture(&automata, None, &history, 4, 0.25);
+        let loaded = save_and_load(&session, "round-trip").unwrap();
+
+        assert_eq!(
+            loaded.rules().unwrap().wolfram_code(),
+            automata.rules().wolfram_code()
+        );
+        assert_eq!(loaded.board().unwrap(), automata.cells());
+        assert_eq!(loaded.history().unwrap(), history);
+        assert_eq!(loaded.rule_options, *automata.rule_options());
+        assert_eq!((loaded.boundary_x, loaded.boundary_y), automata.boundary());
+        assert_eq!((loaded.rule_seed, loaded.board_seed), (7, 8));
+        assert_eq!((loaded.history_length, loaded.step_time), (4, 0.25));
+        assert!(loaded.generations_rule().unwrap().is_none());
+    }
+
+    #[test]
+    fn generations_sessions_keep_high_states() {
+        let mut automata = Automata::with_neighborhood(6, 4, Neighborhood::Moore(1), 0.5);
+        automata.set_generations(parse_generations("B2/S/C40").unwrap());
+        let mut cells = automata.cells();
+        cells[3] = 39;
+        automata.set_cells(&cells).unwrap();
+
+        let session = Session::capture(&automata, Some("B2/S/C40".to_string()), &[], 1, 0.05);
+        assert_eq!(session.cells[0].len(), 12);
+        let loaded = save_and_load(&session, "generations").unwrap();
+        assert_eq!(loaded.board().unwrap(), cells);
+        assert_eq!(loaded.generations_rule().unwrap().unwrap().states(), 40);
+    }
+
+    #[test]
+    fn corrupt_sessions_are_rejected() {
+        let history = [automata().cells()];
+        let valid = || Session::capture(&automata(), None, &history, 2, 0.05);
+        type Corruption = fn(&mut Session);
+        let corruptions: [(&str, Corruption); 9] = [
+            ("version", |session| session.version = SESSION_VERSION + 1),
+            ("states", |session| session.states = MAX_STATES + 1),
+            ("rule", |session| session.rule = "not a number".to_string()),
+            ("row-count", |session| {
+                session.cells.pop();
+            }),
+            ("row-length", |session| session.cells[0].push('0')),
+            ("state", |session| session.cells[1].replace_range(0..1, "3")),
+            ("history", |session| session.history_length = 1),
+            ("history-length", |session| {
+                session.history_length = 100_000;
+                session.history.clear();
+            }),
+            ("lambda", |session| session.rule_options.lambda = Some(1.5)),
+        ];
+        for (name, corrupt) in corruptions {
+            let mut session = valid();
+            corrupt(&mut session);
+            assert!(save_and_load(&session, name).is_err(), "{}", name);
+        }
+
+        let path = std::env::temp_dir().join(format!(
+            "cellular-automata-{}-not-json.json",
+            std::process::id()
+        ));
+        fs::write(&path, "{ \"version\": 2, \"grid_w\": ").unwrap();
+        assert!(Session::load(&path.to_string_lossy()).is_err());
+        let _ = fs::remove_file(&path);
+        assert!(Session::load("/nonexistent/session.json").is_err());
+    }
+}
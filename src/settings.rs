@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::fs;
+
+use crate::config::{
+    BOARD_SEED, GRID_H, GRID_W, HISTORY_LENGTH, MAX_STATES, NEIGHBORHOOD_H, NEIGHBORHOOD_W,
+    RULE_SEED, SPAWN_CHANCE, STATES, STEP_TIME, WINDOW_H, WINDOW_W,
+};
+use crate::rules::max_cells;
+
+// The board and its history are drawn from one texture, which is limited to u16 sides.
+pub fn check_texture_size(
+    grid_w: usize,
+    grid_h: usize,
+    history_length: usize,
+) -> Result<(), String> {
+    if grid_w > u16::MAX as usize || grid_h.saturating_mul(history_length) > u16::MAX as usize {
+        return Err(format!(
+            "board width and height x history length must be at most {}",
+            u16::MAX
+        ));
+    }
+    Ok(())
+}
+
+// Startup parameters. The consts in `config.rs` are the defaults; a TOML file may set any
+// subset of these fields by the same names, and command-line flags override both.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub grid_w: usize,
+    pub grid_h: usize,
+    pub history_length: usize,
+    pub neighborhood_w: usize,
+    pub neighborhood_h: usize,
+    pub states: usize,
+    pub spawn_chance: f32,
+    pub rule_seed: Option<u64>,
+    pub board_seed: Option<u64>,
+    pub step_time: f32,
+    pub window_w: u32,
+    pub window_h: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            grid_w: GRID_W,
+            grid_h: GRID_H,
+            history_length: HISTORY_LENGTH,
+            neighborhood_w: NEIGHBORHOOD_W,
+            neighborhood_h: NEIGHBORHOOD_H,
+            states: STATES,
+            spawn_chance: SPAWN_CHANCE,
+            rule_seed: RULE_SEED,
+            board_seed: BOARD_SEED,
+            step_time: STEP_TIME,
+            window_w: WINDOW_W,
+            window_h: WINDOW_H,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+        toml::from_str(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    // Reports every problem at once, one per line.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if self.grid_w == 0 || self.grid_h == 0 {
+            errors.push("board width and height must be at least 1".to_string());
+        }
+        if self.history_length == 0 {
+            errors.push("history length must be at least 1".to_string());
+        }
+        if let Err(err) = check_texture_size(self.grid_w, self.grid_h, self.history_length) {
+            errors.push(err);
+        }
+        if !(2..=MAX_STATES).contains(&self.states) {
+            errors.push(format!("states must be between 2 and {}", MAX_STATES));
+        } else if self.neighborhood_w == 0
+            || self.neighborhood_h == 0
+            || self.neighborhood_w.saturating_mul(self.neighborhood_h) > max_cells(self.states)
+        {
+            errors.push(format!(
+                "neighborhood must have between 1 and {} cells with {} states",
+                max_cells(self.states),
+                self.states
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.spawn_chance) {
+            errors.push("spawn chance must be between 0 and 1".to_string());
+        }
+        if !(0.001..=5.0).contains(&self.step_time) {
+            errors.push("step time must be between 0.001 and 5 seconds".to_string());
+        }
+        if self.window_w < 200 || self.window_h < 200 {
+            errors.push("window must be at least 200x200".to_string());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use crate::automata::Automata;
+
+pub const MAX_SCALE: usize = 64;
+
+// Blows every pixel of a top-down RGBA image up into a `scale` x `scale` block.
+pub fn upscale(width: usize, height: usize, rgba: &[u8], scale: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(rgba.len() * scale * scale);
+    for row in rgba.chunks(width * 4).take(height) {
+        let mut scaled_row = Vec::with_capacity(row.len() * scale);
+        for pixel in row.chunks(4) {
+            for _ in 0..scale {
+                scaled_row.extend_from_slice(pixel);
+            }
+        }
+        for _ in 0..scale {
+            out.extend_from_slice(&scaled_row);
+        }
+    }
+    out
+}
+
+pub fn check_scale(width: usize, height: usize, scale: usize) -> Result<(), String> {
+    if !(1..=MAX_SCALE).contains(&scale) {
+        return Err(format!("scale must be between 1 and {}", MAX_SCALE));
+    }
+    if width == 0 || height == 0 {
+        return Err("image is empty".to_string());
+    }
+    if width * scale > u32::MAX as usize || height * scale > u32::MAX as usize {
+        return Err("scaled image is too large".to_string());
+    }
+    Ok(())
+}
+
+pub fn write_png(
+    path: &str,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+    scale: usize,
+) -> Result<(), String> {
+    check_scale(width, height, scale)?;
+    let file = File::create(path).map_err(|err| format!("could not write {}: {}", path, err))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        (width * scale) as u32,
+        (height * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&upscale(width, height, rgba, scale)))
+        .map_err(|err| format!("could not write {}: {}", path, err))
+}
+
+// Steps the automata `generations - 1` times and stacks every board under the previous one,
+// the same layout as the history view.
+pub fn spacetime(
+    automata: &mut Automata,
+    generations: usize,
+    color: impl Fn(u8) -> [u8; 4],
+) -> Vec<u8> {
+    let mut rgba = Vec::new();
+    for generation in 0..generations {
+        if generation > 0 {
+            automata.step();
+        }
+        for state in automata.cells() {
+            rgba.extend_from_slice(&color(state));
+        }
+    }
+    rgba
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "parallel")]
+
+use cellular_automata::automata::Automata;
+
+#[test]
+fn parallel_step_matches_serial_for_any_thread_count() {
+    let cases = [(512, 384, 3, 3), (300, 257, 4, 4), (1024, 64, 16, 1)];
+
+    for (grid_w, grid_h, neighborhood_w, neighborhood_h) in cases {
+        let start = Automata::new(grid_w, grid_h, neighborhood_w, neighborhood_h, 0.4);
+        let mut serial = start.clone();
+        for _ in 0..8 {
+            serial.step_serial();
+        }
+
+        for threads in [1, 2, 3, 8] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let mut parallel = start.clone();
+            pool.install(|| {
+                for _ in 0..8 {
+                    parallel.step_parallel();
+                }
+            });
+            assert!(
+                parallel.cells() == serial.cells(),
+                "{}x{} board, {}x{} neighborhood, {} threads",
+                grid_w,
+                grid_h,
+                neighborhood_w,
+                neighborhood_h,
+                threads
+            );
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// The sliding-window `Automata::step` must match `step_reference` for every neighborhood
+// shape, state count and pair of boundary modes, and under Generations rules.
+
+use cellular_automata::automata::{Automata, Boundary};
+use cellular_automata::neighborhood::Neighborhood;
+use cellular_automata::rulestring::parse_generations;
+
+const STEPS: usize = 5;
+
+fn rectangle(width: usize, height: usize) -> Neighborhood {
+    Neighborhood::Rectangle { width, height }
+}
+
+fn assert_matches_reference(automata: &Automata, description: &str) {
+    for boundary_x in Boundary::ALL {
+        for boundary_y in Boundary::ALL {
+            let mut sliding = automata.clone();
+            sliding.set_boundary(boundary_x, boundary_y);
+            let mut reference = sliding.clone();
+            for generation in 0..STEPS {
+                sliding.step();
+                reference.step_reference();
+                assert!(
+                    sliding.cells() == reference.cells(),
+                    "{}, {:?}/{:?} edges: outputs differ at generation {}",
+                    description,
+                    boundary_x,
+                    boundary_y,
+                    generation + 1
+                );
+            }
+        }
+    }
+}
+
+fn check_cases(cases: Vec<(usize, usize, Neighborhood, usize)>) {
+    for (grid_w, grid_h, neighborhood, states) in cases {
+        let automata = Automata::with_states(grid_w, grid_h, neighborhood.clone(), states, 0.5);
+        let description = format!(
+            "{}x{} board, {}, {} states",
+            grid_w,
+            grid_h,
+            neighborhood.label(),
+            states
+        );
+        assert_matches_reference(&automata, &description);
+    }
+}
+
+#[test]
+fn rectangles_match_reference() {
+    check_cases(vec![
+        (256, 1, rectangle(3, 1), 2),
+        (256, 1, rectangle(16, 1), 2),
+        (37, 29, rectangle(3, 3), 2),
+        (37, 29, rectangle(4, 4), 2),
+        (41, 23, rectangle(5, 2), 2),
+        // Narrower than the neighborhood, so the window wraps more than once.
+        (8, 5, rectangle(16, 1), 2),
+    ]);
+}
+
+#[test]
+fn other_shapes_match_reference() {
+    check_cases(vec![
+        (37, 29, Neighborhood::VonNeumann(2), 2),
+        (37, 29, Neighborhood::Hexagonal, 2),
+        (37, 29, Neighborhood::Circular(2), 2),
+        (41, 23, Neighborhood::Cross(3), 2),
+        (
+            41,
+            23,
+            Neighborhood::custom([(-3, 2), (0, 0), (2, -3), (2, 1), (2, 3), (5, 0)]).unwrap(),
+            2,
+        ),
+    ]);
+}
+
+#[test]
+fn more_states_match_reference() {
+    check_cases(vec![
+        (256, 1, rectangle(3, 1), 3),
+        (256, 1, rectangle(5, 1), 5),
+        (37, 29, rectangle(2, 2), 16),
+        (37, 29, Neighborhood::VonNeumann(1), 4),
+        (37, 29, Neighborhood::Hexagonal, 3),
+    ]);
+}
+
+#[test]
+fn generations_rules_match_reference() {
+    for rulestring in ["B2/S/C3", "345/2/4", "B3/S23/C2"] {
+        let rule = parse_generations(rulestring).unwrap();
+        let mut automata = Automata::with_neighborhood(37, 29, Neighborhood::Moore(1), 0.5);
+        automata.set_generations(rule);
+        automata.randomize();
+        assert_matches_reference(&automata, rulestring);
+    }
+}
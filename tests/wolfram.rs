@@ -0,0 +1,51 @@
+use cellular_automata::automata::Automata;
+use cellular_automata::neighborhood::Neighborhood;
+use cellular_automata::rules::RulesCollection;
+use num_bigint::BigUint;
+
+fn rectangle(width: usize, height: usize) -> Neighborhood {
+    Neighborhood::Rectangle { width, height }
+}
+
+#[test]
+fn rule_110_round_trips_and_steps_like_wolfram() {
+    let code = BigUint::from(110u32);
+    let rules = RulesCollection::from_wolfram_code(&code, rectangle(3, 1), 2).unwrap();
+    assert_eq!(rules.wolfram_code(), code);
+
+    let board = [0, 1, 1, 0, 1, 0, 0, 0, 1, 1, 1, 0, 0, 1, 0, 1];
+    let mut automata = Automata::new(board.len(), 1, 3, 1, 0.0);
+    automata.set_rules(rules);
+    automata.set_cells(&board).unwrap();
+    automata.step();
+
+    // Bit `4 * left + 2 * centre + right` of 110 is the new state.
+    let expected: Vec<u8> = (0..board.len())
+        .map(|x| {
+            let left = board[(x + board.len() - 1) % board.len()];
+            let right = board[(x + 1) % board.len()];
+            let index = 4 * left + 2 * board[x] + right;
+            (110u8 >> index) & 1
+        })
+        .collect();
+    assert_eq!(automata.cells(), expected);
+}
+
+#[test]
+fn wolfram_codes_round_trip_for_more_states() {
+    for (code, neighborhood, states) in [
+        ("7625597484986", rectangle(1, 3), 3),
+        ("1234567", rectangle(2, 2), 4),
+        ("0", rectangle(3, 1), 2),
+    ] {
+        let code: BigUint = code.parse().unwrap();
+        let rules = RulesCollection::from_wolfram_code(&code, neighborhood, states).unwrap();
+        assert_eq!(rules.wolfram_code(), code);
+    }
+}
+
+#[test]
+fn wolfram_codes_too_large_for_the_table_are_rejected() {
+    let code = BigUint::from(256u32);
+    assert!(RulesCollection::from_wolfram_code(&code, rectangle(3, 1), 2).is_err());
+}